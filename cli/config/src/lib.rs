@@ -22,39 +22,61 @@ pub struct Config {
 
 impl Config {
     pub fn find_config_file() -> Result<Option<PathBuf>> {
-        if let Ok(path) = env::var("TREE_SITTER_DIR") {
-            let mut path = PathBuf::from(path);
-            path.push("config.json");
-            if !path.exists() {
-                return Ok(None);
-            }
-            if path.is_file() {
-                return Ok(Some(path));
-            }
-        }
+        Ok(Self::find_file(&Self::config_dirs()?, "config.json"))
+    }
 
-        let xdg_path = Self::xdg_config_file()?;
-        if xdg_path.is_file() {
-            return Ok(Some(xdg_path));
-        }
+    /// Finds the file of the named profile, `config.<name>.json`, in the same directories as
+    /// the configuration file.
+    pub fn find_profile_file(name: &str) -> Result<Option<PathBuf>> {
+        Ok(Self::find_file(
+            &Self::config_dirs()?,
+            &Self::profile_file_name(name),
+        ))
+    }
 
-        let legacy_path = dirs::home_dir()
-            .ok_or_else(|| anyhow!("Cannot determine home directory"))?
-            .join(".tree-sitter")
-            .join("config.json");
-        if legacy_path.is_file() {
-            return Ok(Some(legacy_path));
-        }
+    fn find_file(dirs: &[PathBuf], file_name: &str) -> Option<PathBuf> {
+        dirs.iter()
+            .map(|dir| dir.join(file_name))
+            .find(|path| path.is_file())
+    }
 
-        Ok(None)
+    fn profile_file_name(name: &str) -> String {
+        format!("config.{name}.json")
     }
 
-    fn xdg_config_file() -> Result<PathBuf> {
-        let xdg_path = dirs::config_dir()
+    /// The directories that can contain configuration files, in the order in which they are
+    /// searched.
+    fn config_dirs() -> Result<Vec<PathBuf>> {
+        if let Ok(path) = env::var("TREE_SITTER_DIR") {
+            return Ok(vec![PathBuf::from(path)]);
+        }
+        let xdg_dir = dirs::config_dir()
             .ok_or_else(|| anyhow!("Cannot determine config directory"))?
-            .join("tree-sitter")
-            .join("config.json");
-        Ok(xdg_path)
+            .join("tree-sitter");
+        let legacy_dir = dirs::home_dir()
+            .ok_or_else(|| anyhow!("Cannot determine home directory"))?
+            .join(".tree-sitter");
+        Ok(vec![xdg_dir, legacy_dir])
+    }
+
+    /// The names of the profiles in the given directories, sorted and without duplicates.
+    fn profile_names(dirs: &[PathBuf]) -> Vec<String> {
+        let mut names = dirs
+            .iter()
+            .filter_map(|dir| fs::read_dir(dir).ok())
+            .flatten()
+            .filter_map(|entry| {
+                let file_name = entry.ok()?.file_name();
+                let name = file_name
+                    .to_str()?
+                    .strip_prefix("config.")?
+                    .strip_suffix(".json")?;
+                (!name.is_empty()).then(|| name.to_string())
+            })
+            .collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+        names
     }
 
     /// Locates and loads in the user's configuration file.  We search for the configuration file
@@ -69,6 +91,31 @@ impl Config {
         let Some(location) = Self::find_config_file()? else {
             return Self::initial();
         };
+        Self::load_from(location)
+    }
+
+    /// Locates and loads a named configuration profile, which is stored in a file called
+    /// `config.<name>.json`.  Profiles are searched for in the same directories as the
+    /// configuration file, as described in [`Config::load`][].  Unlike the configuration file,
+    /// a profile must exist.
+    pub fn load_profile(name: &str) -> Result<Self> {
+        let dirs = Self::config_dirs()?;
+        let Some(location) = Self::find_file(&dirs, &Self::profile_file_name(name)) else {
+            let profiles = Self::profile_names(&dirs);
+            if profiles.is_empty() {
+                return Err(anyhow!(
+                    "Unknown config profile '{name}'. There are no profiles, which are stored in files called config.<name>.json"
+                ));
+            }
+            return Err(anyhow!(
+                "Unknown config profile '{name}'. The available profiles are: {}",
+                profiles.join(", ")
+            ));
+        };
+        Self::load_from(location)
+    }
+
+    fn load_from(location: PathBuf) -> Result<Self> {
         let content = fs::read_to_string(&location)
             .with_context(|| format!("Failed to read {}", &location.to_string_lossy()))?;
         let config = serde_json::from_str(&content)
@@ -83,13 +130,17 @@ impl Config {
     ///
     /// (Note that this is typically only done by the `tree-sitter init-config` command.)
     pub fn initial() -> Result<Self> {
-        let location = if let Ok(path) = env::var("TREE_SITTER_DIR") {
-            let mut path = PathBuf::from(path);
-            path.push("config.json");
-            path
-        } else {
-            Self::xdg_config_file()?
-        };
+        Self::initial_at("config.json")
+    }
+
+    /// Creates an empty initial configuration for the named profile, which is saved to
+    /// `config.<name>.json` next to the configuration file.  See [`Config::initial`][].
+    pub fn initial_profile(name: &str) -> Result<Self> {
+        Self::initial_at(&Self::profile_file_name(name))
+    }
+
+    fn initial_at(file_name: &str) -> Result<Self> {
+        let location = Self::config_dirs()?.remove(0).join(file_name);
         let config = serde_json::json!({});
         Ok(Self { location, config })
     }
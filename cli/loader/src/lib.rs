@@ -5,15 +5,17 @@ use libloading::{Library, Symbol};
 use once_cell::unsync::OnceCell;
 use regex::{Regex, RegexBuilder};
 use serde::{Deserialize, Deserializer, Serialize};
-use std::collections::HashMap;
+use std::collections::{hash_map::DefaultHasher, HashMap};
 use std::ffi::{OsStr, OsString};
+use std::hash::{Hash, Hasher};
 use std::io::BufReader;
+use std::num::NonZeroUsize;
 use std::ops::Range;
 use std::path::{Path, PathBuf};
 use std::process::Command;
-use std::sync::Mutex;
+use std::sync::{Condvar, Mutex};
 use std::time::SystemTime;
-use std::{env, fs, mem};
+use std::{env, fs, mem, thread};
 use tree_sitter::{Language, QueryError, QueryErrorKind};
 use tree_sitter_highlight::HighlightConfiguration;
 use tree_sitter_tags::{Error as TagsError, TagsConfiguration};
@@ -21,6 +23,8 @@ use which::which;
 
 pub const EMSCRIPTEN_TAG: &str = concat!("docker.io/emscripten/emsdk:", env!("EMSCRIPTEN_VERSION"));
 
+const WASM_OUTPUT_NAME: &str = "output.wasm";
+
 #[derive(Default, Deserialize, Serialize)]
 pub struct Config {
     #[serde(default)]
@@ -29,9 +33,15 @@ pub struct Config {
         deserialize_with = "deserialize_parser_directories"
     )]
     pub parser_directories: Vec<PathBuf>,
+
+    /// Alternative names for language scopes, like `tsx` for `source.tsx`, which can be
+    /// passed to `--scope`.
+    #[serde(default, rename = "scope-aliases")]
+    pub scope_aliases: HashMap<String, String>,
 }
 
-// Replace `~` or `$HOME` with home path string.
+// Replace `~` or `$HOME` with home path string, and expand any other environment
+// variables.
 // (While paths like "~/.tree-sitter/config.json" can be deserialized,
 // they're not valid path for I/O modules.)
 fn deserialize_parser_directories<'de, D>(deserializer: D) -> Result<Vec<PathBuf>, D::Error>
@@ -39,24 +49,64 @@ where
     D: Deserializer<'de>,
 {
     let paths = Vec::<PathBuf>::deserialize(deserializer)?;
-    let Some(home) = dirs::home_dir() else {
-        return Ok(paths);
-    };
+    let home = dirs::home_dir();
     let standardized = paths
         .into_iter()
-        .map(|path| standardize_path(path, &home))
+        .map(|path| standardize_path(path, home.as_deref()))
         .collect();
     Ok(standardized)
 }
 
-fn standardize_path(path: PathBuf, home: &Path) -> PathBuf {
-    if let Ok(p) = path.strip_prefix("~") {
-        return home.join(p);
+fn standardize_path(path: PathBuf, home: Option<&Path>) -> PathBuf {
+    let expand = |path: &Path| match path.to_str() {
+        Some(s) if s.contains('$') => PathBuf::from(expand_env_vars(s)),
+        _ => path.to_path_buf(),
+    };
+    if let Some(home) = home {
+        for prefix in ["~", "$HOME"] {
+            if let Ok(p) = path.strip_prefix(prefix) {
+                return home.join(expand(p));
+            }
+        }
     }
-    if let Ok(p) = path.strip_prefix("$HOME") {
-        return home.join(p);
+    expand(&path)
+}
+
+// Expand `$VAR` and `${VAR}` references to environment variables. Unset variables
+// expand to an empty string, with a warning.
+fn expand_env_vars(path: &str) -> String {
+    let mut result = String::with_capacity(path.len());
+    let mut rest = path;
+    while let Some(i) = rest.find('$') {
+        result.push_str(&rest[..i]);
+        rest = &rest[i + 1..];
+        let (name, len) = if let Some(braced) = rest.strip_prefix('{') {
+            let Some(end) = braced.find('}') else {
+                result.push('$');
+                continue;
+            };
+            (&braced[..end], end + 2)
+        } else {
+            let end = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            (&rest[..end], end)
+        };
+        if name.is_empty() {
+            result.push('$');
+            continue;
+        }
+        if let Ok(value) = env::var(name) {
+            result.push_str(&value);
+        } else {
+            eprintln!(
+                "Warning: the environment variable ${name} in parser directory {path:?} is not set"
+            );
+        }
+        rest = &rest[len..];
     }
-    path
+    result.push_str(rest);
+    result
 }
 
 impl Config {
@@ -69,6 +119,7 @@ impl Config {
                 home_dir.join("src"),
                 home_dir.join("source"),
             ],
+            scope_aliases: HashMap::new(),
         }
     }
 }
@@ -79,6 +130,12 @@ const DYLIB_EXTENSION: &str = "so";
 #[cfg(windows)]
 const DYLIB_EXTENSION: &str = "dll";
 
+#[cfg(unix)]
+const STATIC_LIB_EXTENSION: &str = "a";
+
+#[cfg(windows)]
+const STATIC_LIB_EXTENSION: &str = "lib";
+
 const BUILD_TARGET: &str = env!("BUILD_TARGET");
 
 pub struct LanguageConfiguration<'a> {
@@ -100,20 +157,71 @@ pub struct LanguageConfiguration<'a> {
     use_all_highlight_names: bool,
 }
 
+/// The grammar whose external scanner is replaced with [`Loader::set_scanner_path`].
+pub enum ScannerTarget {
+    /// The language that the given scope or language name refers to.
+    Scope(String),
+    /// The grammar in the given directory.
+    Directory(PathBuf),
+}
+
 pub struct Loader {
     parser_lib_path: PathBuf,
     languages_by_id: Vec<(PathBuf, OnceCell<Language>)>,
     language_configurations: Vec<LanguageConfiguration<'static>>,
     language_configuration_ids_by_file_type: HashMap<String, Vec<usize>>,
     language_configuration_in_current_path: Option<usize>,
+    scope_aliases: HashMap<String, String>,
     highlight_names: Box<Mutex<Vec<String>>>,
     use_all_highlight_names: bool,
     debug_build: bool,
+    static_build: bool,
+    show_build_warnings: bool,
+    verbose: bool,
+    c_compiler: Option<PathBuf>,
+    scanner_override: Option<(PathBuf, ScannerTarget)>,
+    c_flags: Vec<String>,
+    build_jobs: BuildJobs,
 
     #[cfg(feature = "wasm")]
     wasm_store: Mutex<Option<tree_sitter::WasmStore>>,
 }
 
+/// Limits the number of compiler processes that the threads sharing a loader
+/// can run at once.
+struct BuildJobs {
+    available: Mutex<usize>,
+    released: Condvar,
+}
+
+struct BuildJob<'a>(&'a BuildJobs);
+
+impl BuildJobs {
+    fn new(count: usize) -> Self {
+        Self {
+            available: Mutex::new(count.max(1)),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Wait until fewer than the maximum number of jobs are running, and start one.
+    fn start(&self) -> BuildJob<'_> {
+        let mut available = self.available.lock().unwrap();
+        while *available == 0 {
+            available = self.released.wait(available).unwrap();
+        }
+        *available -= 1;
+        BuildJob(self)
+    }
+}
+
+impl Drop for BuildJob<'_> {
+    fn drop(&mut self) {
+        *self.0.available.lock().unwrap() += 1;
+        self.0.released.notify_one();
+    }
+}
+
 unsafe impl Send for Loader {}
 unsafe impl Sync for Loader {}
 
@@ -137,9 +245,19 @@ impl Loader {
             language_configurations: Vec::new(),
             language_configuration_ids_by_file_type: HashMap::new(),
             language_configuration_in_current_path: None,
+            scope_aliases: HashMap::new(),
             highlight_names: Box::new(Mutex::new(Vec::new())),
             use_all_highlight_names: true,
             debug_build: false,
+            static_build: false,
+            show_build_warnings: false,
+            verbose: false,
+            c_compiler: None,
+            scanner_override: None,
+            c_flags: Vec::new(),
+            build_jobs: BuildJobs::new(
+                thread::available_parallelism().map_or(1, NonZeroUsize::get),
+            ),
 
             #[cfg(feature = "wasm")]
             wasm_store: Mutex::default(),
@@ -165,6 +283,7 @@ impl Loader {
             eprintln!("configuration file to indicate where we should look for");
             eprintln!("language grammars.\n");
         }
+        self.scope_aliases.clone_from(&config.scope_aliases);
         for parser_container_dir in &config.parser_directories {
             if let Ok(entries) = fs::read_dir(parser_container_dir) {
                 for entry in entries {
@@ -201,6 +320,44 @@ impl Loader {
         }
     }
 
+    /// Compile the languages at the given path without loading them, and return the
+    /// paths of the compiled libraries. With `use_static_build`, these are static
+    /// libraries, which can be linked into another program, but can't be loaded.
+    pub fn compile_languages_at_path(&mut self, path: &Path) -> Result<Vec<PathBuf>> {
+        let configurations = self.find_language_configurations_at_path(path, true)?;
+        let mut languages = configurations
+            .iter()
+            .map(|c| (c.language_id, c.language_name.clone()))
+            .collect::<Vec<_>>();
+        languages.sort_unstable();
+        languages.dedup();
+        languages
+            .into_iter()
+            .map(|(id, name)| {
+                let src_path = self.languages_by_id[id].0.join("src");
+                self.compile_language_at_path_with_name(&src_path, &[&src_path], &name)
+            })
+            .collect()
+    }
+
+    /// Load the language that the given configuration belongs to, compiling it if needed.
+    pub fn language_for_configuration(
+        &self,
+        configuration: &LanguageConfiguration,
+    ) -> Result<Language> {
+        self.language_for_id(configuration.language_id)
+    }
+
+    /// Find the name of a language that has already been loaded, from the configuration
+    /// that it was loaded for.
+    #[must_use]
+    pub fn language_name(&self, language: &Language) -> Option<&str> {
+        self.language_configurations
+            .iter()
+            .find(|c| self.languages_by_id[c.language_id].1.get() == Some(language))
+            .map(|c| c.language_name.as_str())
+    }
+
     #[must_use]
     pub fn get_all_language_configurations(&self) -> Vec<(&LanguageConfiguration, &Path)> {
         self.language_configurations
@@ -213,13 +370,58 @@ impl Loader {
         &self,
         scope: &str,
     ) -> Result<Option<(Language, &LanguageConfiguration)>> {
-        for configuration in &self.language_configurations {
-            if configuration.scope.as_ref().map_or(false, |s| s == scope) {
+        match self.configurations_for_scope(scope).as_slice() {
+            [] => Ok(None),
+            [configuration] => {
                 let language = self.language_for_id(configuration.language_id)?;
-                return Ok(Some((language, configuration)));
+                Ok(Some((language, configuration)))
             }
+            candidates => Err(anyhow!(
+                "Scope {:?} is ambiguous. It matches:\n{}",
+                self.scope_aliases.get(scope).map_or(scope, String::as_str),
+                candidates
+                    .iter()
+                    .map(|configuration| format!(
+                        "  {} ({})",
+                        configuration.scope.as_deref().unwrap_or("<no scope>"),
+                        configuration.root_path.display()
+                    ))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            )),
         }
-        Ok(None)
+    }
+
+    /// The configurations that the given scope refers to. This is the one with exactly
+    /// that scope, if there is one, and otherwise every configuration whose scope ends
+    /// with the given name (`rust` for `source.rust`) or whose directory is named after it.
+    fn configurations_for_scope(&self, scope: &str) -> Vec<&LanguageConfiguration<'_>> {
+        // Aliases from the config file name other scopes. Anything else is resolved as is.
+        let scope = self.scope_aliases.get(scope).map_or(scope, String::as_str);
+        if let Some(configuration) = self
+            .language_configurations
+            .iter()
+            .find(|configuration| configuration.scope.as_deref() == Some(scope))
+        {
+            return vec![configuration];
+        }
+
+        let suffix = format!(".{scope}");
+        let directory_name = format!("tree-sitter-{scope}");
+        self.language_configurations
+            .iter()
+            .filter(|configuration| {
+                configuration
+                    .scope
+                    .as_ref()
+                    .is_some_and(|s| s.ends_with(&suffix))
+                    || configuration
+                        .root_path
+                        .file_name()
+                        .and_then(|name| name.to_str())
+                        .is_some_and(|name| name == scope || name == directory_name)
+            })
+            .collect()
     }
 
     pub fn language_configuration_for_file_name(
@@ -352,30 +554,35 @@ impl Loader {
         header_paths: &[&Path],
         name: &str,
     ) -> Result<Language> {
-        let mut lib_name = name.to_string();
-        let language_fn_name = format!("tree_sitter_{}", replace_dashes_with_underscores(name));
-        if self.debug_build {
-            lib_name.push_str(".debug._");
+        if self.static_build {
+            return Err(anyhow!(
+                "Cannot load the {name} language from a static library"
+            ));
         }
 
-        fs::create_dir_all(&self.parser_lib_path)?;
+        let language_fn_name = format!("tree_sitter_{}", replace_dashes_with_underscores(name));
 
-        let mut library_path = self.parser_lib_path.join(lib_name);
-        library_path.set_extension(DYLIB_EXTENSION);
+        fs::create_dir_all(&self.parser_lib_path)?;
 
+        let library_path = self.parser_library_path(name, src_path);
         let parser_path = src_path.join("parser.c");
-        let scanner_path = self.get_scanner_path(src_path);
-
-        #[cfg(feature = "wasm")]
-        if self.wasm_store.lock().unwrap().is_some() {
-            library_path.set_extension("wasm");
-        }
+        let scanner_override = self.scanner_override(name, src_path);
+        let scanner_path = self.scanner_path_for_build(name, src_path);
 
-        let recompile = needs_recompile(&library_path, &parser_path, scanner_path.as_deref())
-            .with_context(|| "Failed to compare source and binary timestamps")?;
+        let recompile = self.needs_recompile(
+            &library_path,
+            &parser_path,
+            scanner_path.as_deref(),
+            scanner_override.is_some(),
+        )?;
 
         #[cfg(feature = "wasm")]
         if let Some(wasm_store) = self.wasm_store.lock().unwrap().as_mut() {
+            if scanner_override.is_some() {
+                return Err(anyhow!(
+                    "The scanner can't be replaced when compiling parsers to wasm"
+                ));
+            }
             if recompile {
                 self.compile_parser_to_wasm(
                     name,
@@ -394,7 +601,7 @@ impl Loader {
 
         {
             if recompile {
-                self.compile_parser_to_dylib(
+                self.compile_parser_to_library(
                     header_paths,
                     &parser_path,
                     &scanner_path,
@@ -415,13 +622,106 @@ impl Loader {
         }
     }
 
-    fn compile_parser_to_dylib(
+    /// Compile the language in the given directory, if it has changed since it was
+    /// last compiled, without loading it, and return the path of the library.
+    pub fn compile_language_at_path_with_name(
+        &self,
+        src_path: &Path,
+        header_paths: &[&Path],
+        name: &str,
+    ) -> Result<PathBuf> {
+        fs::create_dir_all(&self.parser_lib_path)?;
+
+        let library_path = self.parser_library_path(name, src_path);
+        let parser_path = src_path.join("parser.c");
+        let scanner_path = self.scanner_path_for_build(name, src_path);
+
+        if self.needs_recompile(
+            &library_path,
+            &parser_path,
+            scanner_path.as_deref(),
+            self.scanner_override(name, src_path).is_some(),
+        )? {
+            self.compile_parser_to_library(
+                header_paths,
+                &parser_path,
+                &scanner_path,
+                &library_path,
+            )?;
+        }
+        Ok(library_path)
+    }
+
+    /// The path of the compiled library for the language with the given name, whose
+    /// sources are in `src_path`.
+    #[must_use]
+    pub fn parser_library_path(&self, name: &str, src_path: &Path) -> PathBuf {
+        // Unix linkers look for static libraries named `lib<name>.a`.
+        let mut lib_name = if self.static_build && cfg!(unix) {
+            format!("lib{name}")
+        } else {
+            name.to_string()
+        };
+        if self.debug_build {
+            lib_name.push_str(".debug");
+        }
+        if self.scanner_override(name, src_path).is_some() {
+            lib_name.push_str(".custom-scanner");
+        }
+        // Parsers built with another compiler or extra flags are stored apart from the
+        // usual ones, under a hash of the compiler and flags, so that changing either of
+        // them doesn't reuse a library that was built with the old ones.
+        if self.c_compiler.is_some() || !self.c_flags.is_empty() {
+            let mut hasher = DefaultHasher::new();
+            self.c_compiler.hash(&mut hasher);
+            self.c_flags.hash(&mut hasher);
+            lib_name.push_str(&format!(".build-{:016x}", hasher.finish()));
+        }
+        lib_name.push_str("._");
+
+        let mut library_path = self.parser_lib_path.join(lib_name);
+        if self.static_build {
+            library_path.set_extension(STATIC_LIB_EXTENSION);
+            return library_path;
+        }
+        library_path.set_extension(DYLIB_EXTENSION);
+
+        #[cfg(feature = "wasm")]
+        if self.wasm_store.lock().unwrap().is_some() {
+            library_path.set_extension("wasm");
+        }
+
+        library_path
+    }
+
+    fn compile_parser_to_library(
         &self,
         header_paths: &[&Path],
         parser_path: &Path,
         scanner_path: &Option<PathBuf>,
         library_path: &PathBuf,
     ) -> Result<(), Error> {
+        // A static library is an archive of object files, which the compiler writes
+        // to its working directory, so the sources must be found from there.
+        let current_dir = env::current_dir()?;
+        let header_paths = header_paths
+            .iter()
+            .map(|path| current_dir.join(path))
+            .collect::<Vec<_>>();
+        let parser_path = &current_dir.join(parser_path);
+        let scanner_path = &scanner_path.as_ref().map(|path| current_dir.join(path));
+        let object_dir = library_path.with_extension("objects");
+        let mut _object_dir_cleanup = None;
+        if self.static_build {
+            if object_dir.exists() {
+                fs::remove_dir_all(&object_dir)?;
+            }
+            fs::create_dir_all(&object_dir)?;
+            // The object files are only needed to create the archive, so they're
+            // removed afterwards, even if compiling or archiving failed.
+            _object_dir_cleanup = Some(RemoveDirOnDrop(object_dir.clone()));
+        }
+
         let mut config = cc::Build::new();
         config
             .cpp(true)
@@ -430,6 +730,9 @@ impl Loader {
             .target(BUILD_TARGET)
             .host(BUILD_TARGET)
             .flag_if_supported("-Werror=implicit-function-declaration");
+        if let Some(c_compiler) = &self.c_compiler {
+            config.compiler(c_compiler);
+        }
         let compiler = config.get_compiler();
         let mut command = Command::new(compiler.path());
         for (key, value) in compiler.env() {
@@ -437,7 +740,13 @@ impl Loader {
         }
 
         if compiler.is_like_msvc() {
-            command.args(["/nologo", "/LD"]);
+            if self.static_build {
+                command
+                    .args(["/nologo", "/c"])
+                    .arg(format!("/Fo{}\\", object_dir.to_string_lossy()));
+            } else {
+                command.args(["/nologo", "/LD"]);
+            }
 
             for path in header_paths {
                 command.arg(format!("/I{}", path.to_string_lossy()));
@@ -448,20 +757,23 @@ impl Loader {
             } else {
                 command.arg("/O2");
             }
+            command.args(&self.c_flags);
             command.arg(parser_path);
             if let Some(scanner_path) = scanner_path.as_ref() {
                 command.arg(scanner_path);
             }
-            command
-                .arg("/link")
-                .arg(format!("/out:{}", library_path.to_str().unwrap()));
+            if !self.static_build {
+                command
+                    .arg("/link")
+                    .arg(format!("/out:{}", library_path.to_str().unwrap()));
+            }
         } else {
-            command
-                .arg("-shared")
-                .arg("-fno-exceptions")
-                .arg("-g")
-                .arg("-o")
-                .arg(library_path);
+            if self.static_build {
+                command.arg("-c").current_dir(&object_dir);
+            } else {
+                command.arg("-shared").arg("-o").arg(library_path);
+            }
+            command.arg("-fno-exceptions").arg("-g");
 
             for path in header_paths {
                 command.arg(format!("-I{}", path.to_string_lossy()));
@@ -480,6 +792,7 @@ impl Loader {
             // For conditional compilation of external scanner code when
             // used internally by `tree-siteer parse` and other sub commands.
             command.arg("-DTREE_SITTER_INTERNAL_BUILD");
+            command.args(&self.c_flags);
 
             if let Some(scanner_path) = scanner_path.as_ref() {
                 if scanner_path.extension() == Some("c".as_ref()) {
@@ -491,9 +804,12 @@ impl Loader {
             command.arg("-xc").arg(parser_path);
         }
 
-        let output = command
-            .output()
-            .with_context(|| "Failed to execute C compiler")?;
+        let output = {
+            let _job = self.build_jobs.start();
+            command
+                .output()
+                .with_context(|| "Failed to execute C compiler")?
+        };
         if !output.status.success() {
             return Err(anyhow!(
                 "Parser compilation failed.\nStdout: {}\nStderr: {}",
@@ -502,6 +818,42 @@ impl Loader {
             ));
         }
 
+        if self.static_build {
+            let mut object_paths = fs::read_dir(&object_dir)?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<Result<Vec<_>, _>>()?;
+            object_paths.sort();
+            let mut archiver = config.try_get_archiver()?;
+            if compiler.is_like_msvc() {
+                archiver.arg(format!("/OUT:{}", library_path.to_str().unwrap()));
+            } else {
+                // The archive is replaced rather than updated, in case a file was removed.
+                if library_path.exists() {
+                    fs::remove_file(library_path)?;
+                }
+                archiver.arg("crs").arg(library_path);
+            }
+            let output = archiver
+                .args(&object_paths)
+                .output()
+                .with_context(|| "Failed to execute archiver")?;
+            if !output.status.success() {
+                return Err(anyhow!(
+                    "Creating the static library failed.\nStdout: {}\nStderr: {}",
+                    String::from_utf8_lossy(&output.stdout),
+                    String::from_utf8_lossy(&output.stderr)
+                ));
+            }
+        }
+
+        if self.show_build_warnings && !output.stderr.is_empty() {
+            eprintln!(
+                "Warnings from compiling {}:\n{}",
+                parser_path.parent().unwrap_or(parser_path).display(),
+                String::from_utf8_lossy(&output.stderr).trim_end()
+            );
+        }
+
         #[cfg(any(target_os = "macos", target_os = "linux"))]
         if scanner_path.is_some() {
             let command = Command::new("nm")
@@ -543,6 +895,38 @@ impl Loader {
         output_path: &PathBuf,
         force_docker: bool,
     ) -> Result<(), Error> {
+        let mut command =
+            self.wasm_compile_command(language_name, src_path, scanner_filename, force_docker)?;
+        if self.verbose {
+            eprintln!("{}", format_command(&command));
+        }
+
+        let output = {
+            let _job = self.build_jobs.start();
+            command.output().context("Failed to run emcc command")?
+        };
+        if !output.status.success() {
+            return Err(anyhow!(
+                "emcc command failed - {}",
+                String::from_utf8_lossy(&output.stderr)
+            ));
+        }
+
+        fs::rename(src_path.join(WASM_OUTPUT_NAME), output_path)
+            .context("failed to rename wasm output file")?;
+        Ok(())
+    }
+
+    /// The command that `compile_parser_to_wasm` runs, using emscripten if it's
+    /// installed, and docker or podman otherwise. It is run in `src_path`, and writes
+    /// the parser to a file called `output.wasm` there.
+    pub fn wasm_compile_command(
+        &self,
+        language_name: &str,
+        src_path: &Path,
+        scanner_filename: Option<&Path>,
+        force_docker: bool,
+    ) -> Result<Command, Error> {
         #[derive(PartialEq, Eq)]
         enum EmccSource {
             Native(PathBuf),
@@ -643,11 +1027,9 @@ impl Loader {
             }
         };
 
-        let output_name = "output.wasm";
-
         command.args([
             "-o",
-            output_name,
+            WASM_OUTPUT_NAME,
             "-Os",
             "-s",
             "WASM=1",
@@ -677,17 +1059,7 @@ impl Loader {
         }
 
         command.arg("parser.c");
-        let output = command.output().context("Failed to run emcc command")?;
-        if !output.status.success() {
-            return Err(anyhow!(
-                "emcc command failed - {}",
-                String::from_utf8_lossy(&output.stderr)
-            ));
-        }
-
-        fs::rename(src_path.join(output_name), output_path)
-            .context("failed to rename wasm output file")?;
-        Ok(())
+        Ok(command)
     }
 
     #[must_use]
@@ -881,6 +1253,11 @@ impl Loader {
                 .push(unsafe { mem::transmute(configuration) });
             self.languages_by_id
                 .push((parser_path.to_owned(), OnceCell::new()));
+
+            if set_current_path_config && self.language_configuration_in_current_path.is_none() {
+                self.language_configuration_in_current_path =
+                    Some(self.language_configurations.len() - 1);
+            }
         }
 
         Ok(&self.language_configurations[initial_language_configuration_count..])
@@ -896,16 +1273,35 @@ impl Loader {
         current_dir: &Path,
         scope: Option<&str>,
     ) -> Result<Language> {
-        if let Some(scope) = scope {
-            if let Some(config) = self
-                .language_configuration_for_scope(scope)
+        Ok(self
+            .select_language_configuration(path, current_dir, scope)?
+            .0)
+    }
+
+    /// Choose the language for the given file, along with its configuration. This is
+    /// the language with the given scope, if there is one, then the language for the
+    /// file's name, then the grammar in the current directory.
+    pub fn select_language_configuration(
+        &mut self,
+        path: &Path,
+        current_dir: &Path,
+        scope: Option<&str>,
+    ) -> Result<(Language, &LanguageConfiguration)> {
+        // The grammar in the current directory is only needed when nothing else
+        // matches, and it has to be added before any configuration is borrowed.
+        if scope.is_none()
+            && self.language_configuration_in_current_path.is_none()
+            && self.language_configuration_for_file_name(path)?.is_none()
+        {
+            self.find_language_configurations_at_path(current_dir, true)
+                .with_context(|| "Failed to load language in current directory")?;
+        }
+
+        let selection = if let Some(scope) = scope {
+            self.language_configuration_for_scope(scope)
                 .with_context(|| format!("Failed to load language for scope '{scope}'"))?
-            {
-                Ok(config.0)
-            } else {
-                Err(anyhow!("Unknown scope '{scope}'"))
-            }
-        } else if let Some((lang, _)) = self
+                .ok_or_else(|| anyhow!("Unknown scope '{scope}'"))?
+        } else if let Some(selection) = self
             .language_configuration_for_file_name(path)
             .with_context(|| {
                 format!(
@@ -914,30 +1310,125 @@ impl Loader {
                 )
             })?
         {
-            Ok(lang)
+            selection
         } else if let Some(id) = self.language_configuration_in_current_path {
-            Ok(self.language_for_id(self.language_configurations[id].language_id)?)
-        } else if let Some(lang) = self
-            .languages_at_path(current_dir)
-            .with_context(|| "Failed to load language in current directory")?
-            .first()
-            .cloned()
-        {
-            Ok(lang)
+            let config = &self.language_configurations[id];
+            (self.language_for_id(config.language_id)?, config)
         } else {
-            Err(anyhow!("No language found"))
-        }
+            return Err(anyhow!("No language found for path {path:?}"));
+        };
+        self.log_language_selection(path, selection.1);
+        Ok(selection)
     }
 
     pub fn use_debug_build(&mut self, flag: bool) {
         self.debug_build = flag;
     }
 
+    /// Compile parsers into static libraries instead of dynamic ones. These can only
+    /// be compiled with `compile_languages_at_path`, not loaded.
+    pub fn use_static_build(&mut self, flag: bool) {
+        self.static_build = flag;
+    }
+
+    pub fn show_build_warnings(&mut self, flag: bool) {
+        self.show_build_warnings = flag;
+    }
+
+    pub fn set_verbose(&mut self, flag: bool) {
+        self.verbose = flag;
+    }
+
+    /// Compile parsers with the given C compiler instead of the one that `cc` finds.
+    /// Their libraries are named after the compiler and flags, so they aren't mixed up
+    /// with the usual ones.
+    pub fn set_c_compiler(&mut self, path: Option<PathBuf>) {
+        self.c_compiler = path;
+    }
+
+    /// Compile the given grammar with this external scanner instead of the one in its `src`
+    /// directory. Other grammars are compiled as usual. The parser is stored apart from the
+    /// usual one, and is always recompiled, since the library doesn't record which scanner
+    /// it was built with.
+    pub fn set_scanner_path(&mut self, path: PathBuf, target: ScannerTarget) {
+        // Directories are compared after resolving symlinks and relative paths.
+        let target = match target {
+            ScannerTarget::Directory(dir) => {
+                ScannerTarget::Directory(fs::canonicalize(&dir).unwrap_or(dir))
+            }
+            target @ ScannerTarget::Scope(_) => target,
+        };
+        self.scanner_override = Some((path, target));
+    }
+
+    /// Pass these flags to the C compiler after the default ones, so they can
+    /// override them.
+    pub fn set_c_flags(&mut self, flags: Vec<String>) {
+        self.c_flags = flags;
+    }
+
+    /// Run at most this many compiler processes at once. By default, this is the
+    /// number of CPUs.
+    pub fn set_build_jobs(&mut self, count: usize) {
+        self.build_jobs = BuildJobs::new(count);
+    }
+
+    /// In verbose mode, print the scope and parser library that were selected for a file.
+    pub fn log_language_selection(&self, path: &Path, config: &LanguageConfiguration) {
+        if self.verbose {
+            eprintln!(
+                "{}: using scope {} from {}",
+                path.display(),
+                config.scope.as_deref().unwrap_or("<none>"),
+                self.parser_library_path(
+                    &config.language_name,
+                    &self.languages_by_id[config.language_id].0.join("src")
+                )
+                .display()
+            );
+        }
+    }
+
     #[cfg(feature = "wasm")]
     pub fn use_wasm(&mut self, engine: tree_sitter::wasmtime::Engine) {
         *self.wasm_store.lock().unwrap() = Some(tree_sitter::WasmStore::new(engine).unwrap());
     }
 
+    /// The scanner that replaces the one of the language with the given name, whose
+    /// sources are in `src_path`, if the override applies to that language.
+    fn scanner_override(&self, name: &str, src_path: &Path) -> Option<&Path> {
+        let (scanner_path, target) = self.scanner_override.as_ref()?;
+        let applies = match target {
+            ScannerTarget::Scope(scope) => self
+                .configurations_for_scope(scope)
+                .iter()
+                .any(|configuration| configuration.language_name == name),
+            ScannerTarget::Directory(dir) => fs::canonicalize(src_path)
+                .is_ok_and(|src_path| src_path.parent() == Some(dir.as_path())),
+        };
+        applies.then_some(scanner_path.as_path())
+    }
+
+    fn scanner_path_for_build(&self, name: &str, src_path: &Path) -> Option<PathBuf> {
+        self.scanner_override(name, src_path)
+            .map(Path::to_path_buf)
+            .or_else(|| self.get_scanner_path(src_path))
+    }
+
+    fn needs_recompile(
+        &self,
+        library_path: &Path,
+        parser_path: &Path,
+        scanner_path: Option<&Path>,
+        has_scanner_override: bool,
+    ) -> Result<bool> {
+        if has_scanner_override {
+            return Ok(true);
+        }
+        needs_recompile(library_path, parser_path, scanner_path)
+            .with_context(|| "Failed to compare source and binary timestamps")
+    }
+
     #[must_use]
     pub fn get_scanner_path(&self, src_path: &Path) -> Option<PathBuf> {
         let mut path = src_path.join("scanner.c");
@@ -1066,11 +1557,17 @@ impl<'a> LanguageConfiguration<'a> {
             .map(Option::as_ref);
     }
 
-    pub fn tags_config(&self, language: Language) -> Result<Option<&TagsConfiguration>> {
+    /// Load the tags configuration of the language. If `paths` is given, the tags query
+    /// is read from those files instead of the configured ones.
+    pub fn tags_config(
+        &self,
+        language: Language,
+        paths: Option<&[String]>,
+    ) -> Result<Option<&TagsConfiguration>> {
         self.tags_config
             .get_or_try_init(|| {
                 let (tags_query, tags_ranges) =
-                    self.read_queries(self.tags_filenames.as_deref(), "tags.scm")?;
+                    self.read_queries(paths.or(self.tags_filenames.as_deref()), "tags.scm")?;
                 let (locals_query, locals_ranges) =
                     self.read_queries(self.locals_filenames.as_deref(), "locals.scm")?;
                 if tags_query.is_empty() {
@@ -1104,6 +1601,24 @@ impl<'a> LanguageConfiguration<'a> {
             .map(Option::as_ref)
     }
 
+    /// The paths of the query files with the given name, such as `highlights` or
+    /// `tags`, either as listed in the grammar's `package.json` or the default
+    /// `queries/{name}.scm` file, if it exists.
+    #[must_use]
+    pub fn query_paths(&self, name: &str) -> Vec<PathBuf> {
+        let filenames = match name {
+            "highlights" => self.highlights_filenames.as_deref(),
+            "injections" => self.injections_filenames.as_deref(),
+            "locals" => self.locals_filenames.as_deref(),
+            "tags" => self.tags_filenames.as_deref(),
+            _ => None,
+        };
+        self.resolve_query_paths(filenames, &format!("{name}.scm"))
+            .into_iter()
+            .map(|(_, path)| path)
+            .collect()
+    }
+
     fn include_path_in_query_error(
         mut error: QueryError,
         ranges: &[(String, Range<usize>)],
@@ -1123,6 +1638,28 @@ impl<'a> LanguageConfiguration<'a> {
         Error::from(error).context(format!("Error in query file {path:?}"))
     }
 
+    /// The query files to read, each with the name that is shown in errors: the given
+    /// paths, relative to the grammar, or else `queries/{default_path}`, if it exists.
+    fn resolve_query_paths(
+        &self,
+        paths: Option<&[String]>,
+        default_path: &str,
+    ) -> Vec<(String, PathBuf)> {
+        if let Some(paths) = paths {
+            paths
+                .iter()
+                .map(|path| (path.clone(), self.root_path.join(path)))
+                .collect()
+        } else {
+            let path = self.root_path.join("queries").join(default_path);
+            if path.exists() {
+                vec![(default_path.to_string(), path)]
+            } else {
+                Vec::new()
+            }
+        }
+    }
+
     #[allow(clippy::type_complexity)]
     fn read_queries(
         &self,
@@ -1131,28 +1668,26 @@ impl<'a> LanguageConfiguration<'a> {
     ) -> Result<(String, Vec<(String, Range<usize>)>)> {
         let mut query = String::new();
         let mut path_ranges = Vec::new();
-        if let Some(paths) = paths {
-            for path in paths {
-                let abs_path = self.root_path.join(path);
-                let prev_query_len = query.len();
-                query += &fs::read_to_string(&abs_path)
-                    .with_context(|| format!("Failed to read query file {path:?}"))?;
-                path_ranges.push((path.clone(), prev_query_len..query.len()));
-            }
-        } else {
-            let queries_path = self.root_path.join("queries");
-            let path = queries_path.join(default_path);
-            if path.exists() {
-                query = fs::read_to_string(&path)
-                    .with_context(|| format!("Failed to read query file {path:?}"))?;
-                path_ranges.push((default_path.to_string(), 0..query.len()));
-            }
+        for (path, abs_path) in self.resolve_query_paths(paths, default_path) {
+            let prev_query_len = query.len();
+            query += &fs::read_to_string(&abs_path)
+                .with_context(|| format!("Failed to read query file {path:?}"))?;
+            path_ranges.push((path, prev_query_len..query.len()));
         }
 
         Ok((query, path_ranges))
     }
 }
 
+/// Removes a directory, along with everything in it, when dropped.
+struct RemoveDirOnDrop(PathBuf);
+
+impl Drop for RemoveDirOnDrop {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.0).ok();
+    }
+}
+
 fn needs_recompile(
     lib_path: &Path,
     parser_c_path: &Path,
@@ -1177,6 +1712,37 @@ fn mtime(path: &Path) -> Result<SystemTime> {
     Ok(fs::metadata(path)?.modified()?)
 }
 
+/// Format a command so that it can be pasted into a shell, including its working
+/// directory and the environment variables that it sets.
+#[must_use]
+pub fn format_command(command: &Command) -> String {
+    fn quote(arg: &OsStr) -> String {
+        let arg = arg.to_string_lossy();
+        if !arg.is_empty()
+            && arg
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-_./:=,@+%".contains(c))
+        {
+            arg.into_owned()
+        } else {
+            format!("'{}'", arg.replace('\'', "'\\''"))
+        }
+    }
+
+    let mut words = Vec::new();
+    if let Some(dir) = command.get_current_dir() {
+        words.push(format!("cd {} &&", quote(dir.as_os_str())));
+    }
+    for (key, value) in command.get_envs() {
+        if let Some(value) = value {
+            words.push(format!("{}={}", key.to_string_lossy(), quote(value)));
+        }
+    }
+    words.push(quote(command.get_program()));
+    words.extend(command.get_args().map(quote));
+    words.join(" ")
+}
+
 fn replace_dashes_with_underscores(name: &str) -> String {
     let mut result = String::with_capacity(name.len());
     for c in name.chars() {
@@ -1188,3 +1754,61 @@ fn replace_dashes_with_underscores(name: &str) -> String {
     }
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_env_vars() {
+        env::set_var("TREE_SITTER_TEST_PARSERS", "/opt/parsers");
+        assert_eq!(
+            expand_env_vars("$TREE_SITTER_TEST_PARSERS/rust"),
+            "/opt/parsers/rust"
+        );
+        assert_eq!(
+            expand_env_vars("${TREE_SITTER_TEST_PARSERS}-old/rust"),
+            "/opt/parsers-old/rust"
+        );
+    }
+
+    #[test]
+    fn test_expand_env_vars_that_are_unset_or_malformed() {
+        env::remove_var("TREE_SITTER_TEST_UNSET");
+        assert_eq!(expand_env_vars("/a/$TREE_SITTER_TEST_UNSET/b"), "/a//b");
+        assert_eq!(expand_env_vars("/a/${TREE_SITTER_TEST_UNSET}/b"), "/a//b");
+
+        // A `$` that isn't followed by a name, or a brace that isn't closed, is kept.
+        assert_eq!(expand_env_vars("/a/$/b"), "/a/$/b");
+        assert_eq!(expand_env_vars("/a/${}/b"), "/a/${}/b");
+        assert_eq!(expand_env_vars("/a/${UNCLOSED/b"), "/a/${UNCLOSED/b");
+    }
+
+    #[test]
+    fn test_standardize_path() {
+        let home = Path::new("/home/user");
+        env::set_var("TREE_SITTER_TEST_GRAMMARS", "grammars");
+        assert_eq!(
+            standardize_path(PathBuf::from("~/parsers"), Some(home)),
+            Path::new("/home/user/parsers")
+        );
+        assert_eq!(
+            standardize_path(PathBuf::from("$HOME/parsers"), Some(home)),
+            Path::new("/home/user/parsers")
+        );
+        assert_eq!(
+            standardize_path(PathBuf::from("~/$TREE_SITTER_TEST_GRAMMARS"), Some(home)),
+            Path::new("/home/user/grammars")
+        );
+
+        // Only a whole `~` component refers to the home directory.
+        assert_eq!(
+            standardize_path(PathBuf::from("~other/parsers"), Some(home)),
+            Path::new("~other/parsers")
+        );
+        assert_eq!(
+            standardize_path(PathBuf::from("~/parsers"), None),
+            Path::new("~/parsers")
+        );
+    }
+}
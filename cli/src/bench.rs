@@ -0,0 +1,147 @@
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::Path,
+    time::{Duration, Instant},
+};
+use tree_sitter::{Language, Parser};
+
+pub struct BenchOptions<'a> {
+    pub iterations: usize,
+    pub warmup: usize,
+    pub baseline: Option<&'a Path>,
+}
+
+/// The mean parse time of each file in an earlier run, which later runs are
+/// compared against.
+#[derive(Default, Deserialize, Serialize)]
+struct Baseline {
+    mean_ns: BTreeMap<String, u64>,
+}
+
+struct Summary {
+    mean: Duration,
+    stddev: Duration,
+    min: Duration,
+    max: Duration,
+}
+
+impl Summary {
+    fn new(durations: &[Duration]) -> Self {
+        let secs = durations
+            .iter()
+            .map(Duration::as_secs_f64)
+            .collect::<Vec<_>>();
+        let mean = secs.iter().sum::<f64>() / secs.len() as f64;
+        let variance = secs.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / secs.len() as f64;
+        Self {
+            mean: Duration::from_secs_f64(mean),
+            stddev: Duration::from_secs_f64(variance.sqrt()),
+            min: durations.iter().min().copied().unwrap_or_default(),
+            max: durations.iter().max().copied().unwrap_or_default(),
+        }
+    }
+}
+
+/// Parse each file `opts.warmup` times without measuring, then `opts.iterations`
+/// times, and print the timing of each file along with the overall throughput.
+///
+/// If `opts.baseline` names a file that exists, each file's mean time is compared
+/// against the one stored there. Otherwise, this run's results are stored in it.
+pub fn bench_files_at_paths(
+    language: &Language,
+    paths: &[String],
+    opts: &BenchOptions,
+) -> Result<()> {
+    if opts.iterations == 0 {
+        return Err(anyhow!("The number of iterations must be at least 1"));
+    }
+
+    let mut parser = Parser::new();
+    parser.set_language(language)?;
+
+    let baseline = match opts.baseline {
+        Some(path) if path.exists() => {
+            let contents = fs::read_to_string(path)
+                .with_context(|| format!("Failed to read baseline {path:?}"))?;
+            Some(
+                serde_json::from_str::<Baseline>(&contents)
+                    .with_context(|| format!("Invalid baseline {path:?}"))?,
+            )
+        }
+        _ => None,
+    };
+
+    let max_path_length = paths.iter().map(|p| p.chars().count()).max().unwrap_or(0);
+    let mut results = Baseline::default();
+    let mut total_bytes = 0;
+    let mut total_duration = Duration::ZERO;
+    for path in paths {
+        let source_code =
+            fs::read(path).with_context(|| format!("Error reading source file {path:?}"))?;
+
+        for _ in 0..opts.warmup {
+            parse(&mut parser, &source_code, path)?;
+        }
+        let mut durations = Vec::with_capacity(opts.iterations);
+        for _ in 0..opts.iterations {
+            let start = Instant::now();
+            parse(&mut parser, &source_code, path)?;
+            durations.push(start.elapsed());
+        }
+
+        let summary = Summary::new(&durations);
+        total_bytes += source_code.len() * opts.iterations;
+        total_duration += durations.iter().sum::<Duration>();
+
+        print!(
+            "{path:width$}\tmean: {:>8.3} ms\tstddev: {:>8.3} ms\tmin: {:>8.3} ms\tmax: {:>8.3} ms",
+            summary.mean.as_secs_f64() * 1e3,
+            summary.stddev.as_secs_f64() * 1e3,
+            summary.min.as_secs_f64() * 1e3,
+            summary.max.as_secs_f64() * 1e3,
+            width = max_path_length
+        );
+        if let Some(previous_ns) = baseline.as_ref().and_then(|b| b.mean_ns.get(path)) {
+            let previous_ns = *previous_ns as f64;
+            let change = (summary.mean.as_nanos() as f64 - previous_ns) / previous_ns * 100.0;
+            print!("\tchange: {change:>+6.1}%");
+        }
+        println!();
+
+        results
+            .mean_ns
+            .insert(path.clone(), summary.mean.as_nanos() as u64);
+    }
+
+    let total_secs = total_duration.as_secs_f64();
+    println!(
+        "\nTotal: {} files, {} iterations each, {:.0} bytes/sec",
+        paths.len(),
+        opts.iterations,
+        if total_secs != 0.0 {
+            total_bytes as f64 / total_secs
+        } else {
+            0.0
+        }
+    );
+
+    if let Some(path) = opts.baseline {
+        if baseline.is_none() {
+            fs::write(path, serde_json::to_string_pretty(&results)?)
+                .with_context(|| format!("Failed to write baseline {path:?}"))?;
+            println!("Saved the baseline to {}", path.display());
+        }
+    }
+
+    Ok(())
+}
+
+fn parse(parser: &mut Parser, source_code: &[u8], path: &str) -> Result<()> {
+    parser
+        .parse(source_code, None)
+        .map(drop)
+        .ok_or_else(|| anyhow!("Parsing of {path:?} was cancelled"))
+}
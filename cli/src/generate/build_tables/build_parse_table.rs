@@ -12,7 +12,7 @@ use crate::generate::tables::{
 };
 use anyhow::{anyhow, Result};
 use std::cmp::Ordering;
-use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::fmt::Write;
 use std::hash::BuildHasherDefault;
 use std::u32;
@@ -57,7 +57,7 @@ struct ParseTableBuilder<'a> {
     parse_state_info_by_id: Vec<ParseStateInfo<'a>>,
     parse_state_queue: VecDeque<ParseStateQueueEntry>,
     non_terminal_extra_states: Vec<(Symbol, usize)>,
-    actual_conflicts: HashSet<Vec<Symbol>>,
+    actual_conflicts: BTreeSet<Vec<Symbol>>,
     parse_table: ParseTable,
 }
 
@@ -130,6 +130,7 @@ impl<'a> ParseTableBuilder<'a> {
 
         if !self.actual_conflicts.is_empty() {
             println!("Warning: unnecessary conflicts");
+            crate::generate::record_warning();
             for conflict in &self.actual_conflicts {
                 println!(
                     "  {}",
@@ -16,7 +16,7 @@ use self::grammars::{InlinedProductionMap, LexicalGrammar, SyntaxGrammar};
 use self::parse_grammar::parse_grammar;
 use self::prepare_grammar::prepare_grammar;
 use self::render::render_c_code;
-use self::rules::AliasMap;
+use self::rules::{AliasMap, SymbolType};
 use anyhow::{anyhow, Context, Result};
 use lazy_static::lazy_static;
 use regex::{Regex, RegexBuilder};
@@ -24,6 +24,7 @@ use semver::Version;
 use std::io::Write;
 use std::path::Path;
 use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::{env, fs};
 
 lazy_static! {
@@ -33,9 +34,30 @@ lazy_static! {
         .unwrap();
 }
 
+/// The number of warnings that have been printed while processing grammars, so that
+/// `generate --werror` can fail once all of them have been printed.
+static WARNING_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+fn record_warning() {
+    WARNING_COUNT.fetch_add(1, Ordering::Relaxed);
+}
+
+#[must_use]
+pub fn warning_count() -> usize {
+    WARNING_COUNT.load(Ordering::Relaxed)
+}
+
 struct GeneratedParser {
     c_code: String,
     node_types_json: String,
+    stats: TableStats,
+}
+
+struct TableStats {
+    parse_state_count: usize,
+    symbol_count: usize,
+    parse_action_count: usize,
+    lex_state_count: usize,
 }
 
 pub fn generate_parser_in_directory(
@@ -45,18 +67,13 @@ pub fn generate_parser_in_directory(
     generate_bindings: bool,
     report_symbol_name: Option<&str>,
     js_runtime: Option<&str>,
+    print_stats: bool,
 ) -> Result<()> {
     let src_path = repo_path.join("src");
     let header_path = src_path.join("tree_sitter");
 
     // Read the grammar.json.
-    let grammar_json = if let Some(path) = grammar_path {
-        load_grammar_file(path.as_ref(), js_runtime)?
-    } else {
-        let grammar_js_path =
-            grammar_path.map_or(repo_path.join("grammar.js"), std::convert::Into::into);
-        load_grammar_file(&grammar_js_path, js_runtime)?
-    };
+    let grammar_json = load_grammar_in_directory(repo_path, grammar_path, js_runtime)?;
 
     // Ensure that the output directories exist.
     fs::create_dir_all(&src_path)?;
@@ -77,6 +94,7 @@ pub fn generate_parser_in_directory(
     let GeneratedParser {
         c_code,
         node_types_json,
+        stats,
     } = generate_parser_for_grammar_with_opts(
         &language_name,
         syntax_grammar,
@@ -87,6 +105,14 @@ pub fn generate_parser_in_directory(
         report_symbol_name,
     )?;
 
+    if print_stats {
+        eprintln!("parse states: {}", stats.parse_state_count);
+        eprintln!("symbols: {}", stats.symbol_count);
+        eprintln!("parse actions: {}", stats.parse_action_count);
+        eprintln!("lex states: {}", stats.lex_state_count);
+        eprintln!("parser.c size: {} bytes", c_code.len());
+    }
+
     write_file(&src_path.join("parser.c"), c_code)?;
     write_file(&src_path.join("node-types.json"), node_types_json)?;
     write_file(&header_path.join("parser.h"), tree_sitter::PARSER_HEADER)?;
@@ -98,6 +124,80 @@ pub fn generate_parser_in_directory(
     Ok(())
 }
 
+/// Print the symbol table of the grammar in the given directory, and report on the
+/// parse states of the given rule, without writing any files.
+pub fn inspect_grammar_in_directory(
+    repo_path: &Path,
+    grammar_path: Option<&str>,
+    report_symbol_name: Option<&str>,
+    js_runtime: Option<&str>,
+) -> Result<()> {
+    let grammar_json = load_grammar_in_directory(repo_path, grammar_path, js_runtime)?;
+    let input_grammar = parse_grammar(&grammar_json)?;
+    let (syntax_grammar, lexical_grammar, inlines, simple_aliases) =
+        prepare_grammar(&input_grammar)?;
+    if let Some(name) = report_symbol_name {
+        if name != "*" && !syntax_grammar.variables.iter().any(|v| v.name == name) {
+            let names = syntax_grammar
+                .variables
+                .iter()
+                .map(|v| v.name.as_str())
+                .collect::<Vec<_>>();
+            return Err(anyhow!(
+                "Unknown rule '{name}'. The rules are: {}",
+                names.join(", ")
+            ));
+        }
+    }
+    let variable_info =
+        node_types::get_variable_info(&syntax_grammar, &lexical_grammar, &simple_aliases)?;
+    // The report always starts with the number of parse states of each rule, on stderr.
+    // Without `--rule`, the empty name doesn't match any rule, so that is all that the
+    // report contains, and none of the states themselves are listed.
+    let (parse_table, ..) = build_tables(
+        &syntax_grammar,
+        &lexical_grammar,
+        &simple_aliases,
+        &variable_info,
+        &inlines,
+        Some(report_symbol_name.unwrap_or("")),
+    )?;
+
+    println!("symbols:");
+    for (i, symbol) in parse_table.symbols.iter().enumerate() {
+        let (kind, name) = match symbol.kind {
+            SymbolType::End | SymbolType::EndOfNonTerminalExtra => ("end", "end"),
+            SymbolType::Terminal => (
+                "terminal",
+                lexical_grammar.variables[symbol.index].name.as_str(),
+            ),
+            SymbolType::External => (
+                "external",
+                syntax_grammar.external_tokens[symbol.index].name.as_str(),
+            ),
+            SymbolType::NonTerminal => (
+                "non-terminal",
+                syntax_grammar.variables[symbol.index].name.as_str(),
+            ),
+        };
+        println!("{i}\t{kind:<12}\t{name}");
+    }
+
+    Ok(())
+}
+
+fn load_grammar_in_directory(
+    repo_path: &Path,
+    grammar_path: Option<&str>,
+    js_runtime: Option<&str>,
+) -> Result<String> {
+    if let Some(path) = grammar_path {
+        load_grammar_file(path.as_ref(), js_runtime)
+    } else {
+        load_grammar_file(&repo_path.join("grammar.js"), js_runtime)
+    }
+}
+
 pub fn generate_parser_for_grammar(grammar_json: &str) -> Result<(String, String)> {
     let grammar_json = JSON_COMMENT_REGEX.replace_all(grammar_json, "\n");
     let input_grammar = parse_grammar(&grammar_json)?;
@@ -140,6 +240,23 @@ fn generate_parser_for_grammar_with_opts(
         inlines,
         report_symbol_name,
     )?;
+    let stats = TableStats {
+        parse_state_count: parse_table.states.len(),
+        symbol_count: parse_table.symbols.len(),
+        parse_action_count: parse_table
+            .states
+            .iter()
+            .map(|state| {
+                state
+                    .terminal_entries
+                    .values()
+                    .map(|entry| entry.actions.len())
+                    .sum::<usize>()
+                    + state.nonterminal_entries.len()
+            })
+            .sum(),
+        lex_state_count: main_lex_table.states.len() + keyword_lex_table.states.len(),
+    };
     let c_code = render_c_code(
         name,
         parse_table,
@@ -154,6 +271,7 @@ fn generate_parser_for_grammar_with_opts(
     Ok(GeneratedParser {
         c_code,
         node_types_json: serde_json::to_string_pretty(&node_types_json).unwrap(),
+        stats,
     })
 }
 
@@ -3,7 +3,7 @@ use super::rules::{Alias, AliasMap, Symbol, SymbolType};
 use anyhow::{anyhow, Result};
 use serde::Serialize;
 use std::cmp::Ordering;
-use std::collections::{BTreeMap, HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum ChildType {
@@ -422,10 +422,12 @@ pub fn generate_node_types_json(
         }
     };
 
-    let mut aliases_by_symbol = HashMap::new();
+    // Use ordered collections here, so that the order in which aliased entries are
+    // merged into the JSON does not depend on hashing.
+    let mut aliases_by_symbol = BTreeMap::new();
     for (symbol, alias) in default_aliases {
         aliases_by_symbol.insert(*symbol, {
-            let mut aliases = HashSet::new();
+            let mut aliases = BTreeSet::new();
             aliases.insert(Some(alias.clone()));
             aliases
         });
@@ -434,7 +436,7 @@ pub fn generate_node_types_json(
         if !default_aliases.contains_key(extra_symbol) {
             aliases_by_symbol
                 .entry(*extra_symbol)
-                .or_insert_with(HashSet::new)
+                .or_insert_with(BTreeSet::new)
                 .insert(None);
         }
     }
@@ -443,7 +445,7 @@ pub fn generate_node_types_json(
             for step in &production.steps {
                 aliases_by_symbol
                     .entry(step.symbol)
-                    .or_insert_with(HashSet::new)
+                    .or_insert_with(BTreeSet::new)
                     .insert(
                         step.alias
                             .as_ref()
@@ -492,7 +494,7 @@ pub fn generate_node_types_json(
             // contributes to multiple entries in the final JSON.
             for alias in aliases_by_symbol
                 .get(&Symbol::non_terminal(i))
-                .unwrap_or(&HashSet::new())
+                .unwrap_or(&BTreeSet::new())
             {
                 let kind;
                 let is_named;
@@ -584,7 +586,7 @@ pub fn generate_node_types_json(
 
     let mut anonymous_node_types = Vec::new();
 
-    let empty = HashSet::new();
+    let empty = BTreeSet::new();
     let regular_tokens = lexical_grammar
         .variables
         .iter()
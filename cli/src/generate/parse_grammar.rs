@@ -154,6 +154,7 @@ fn parse_rule(json: RuleJSON) -> Rule {
                             *c != 'u' // silently ignore unicode flag
                         } else {
                             eprintln!("Warning: unsupported flag {c}");
+                            super::record_warning();
                             false
                         }
                     })
@@ -1,5 +1,7 @@
+use super::util;
 use ansi_term::Color;
-use anyhow::Result;
+use anyhow::{anyhow, Context, Result};
+use difference::{Changeset, Difference};
 use lazy_static::lazy_static;
 use serde::ser::SerializeMap;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
@@ -9,6 +11,7 @@ use std::fmt::Write;
 use std::sync::atomic::AtomicUsize;
 use std::time::Instant;
 use std::{fs, io, path, str, usize};
+use tree_sitter::Point;
 use tree_sitter_highlight::{HighlightConfiguration, HighlightEvent, Highlighter, HtmlRenderer};
 use tree_sitter_loader::Loader;
 
@@ -29,6 +32,12 @@ pub const HTML_HEADER: &str = "
     .line {
       white-space: pre;
     }
+    .diff-added {
+      background-color: #e6ffec;
+    }
+    .diff-removed {
+      background-color: #ffebe9;
+    }
   </style>
 </head>
 <body>
@@ -38,6 +47,33 @@ pub const HTML_FOOTER: &str = "
 </body>
 ";
 
+/// The HTML header, with the given CSS `font-family` value instead of `monospace`.
+///
+/// Only font names, quoted or not, separated by commas are accepted, so that the
+/// value can't break out of the stylesheet.
+pub fn html_header_with_font(font_family: &str) -> Result<String> {
+    let is_valid = !font_family.trim().is_empty()
+        && font_family.split(',').all(|family| {
+            let family = family.trim();
+            let name = family
+                .strip_prefix('"')
+                .and_then(|f| f.strip_suffix('"'))
+                .or_else(|| family.strip_prefix('\'').and_then(|f| f.strip_suffix('\'')))
+                .unwrap_or(family);
+            !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || matches!(c, ' ' | '-' | '_' | '.'))
+        });
+    if !is_valid {
+        return Err(anyhow!("Invalid font family {font_family:?}"));
+    }
+    Ok(HTML_HEADER.replace(
+        "font-family: monospace",
+        &format!("font-family: {}", font_family.trim()),
+    ))
+}
+
 lazy_static! {
     static ref CSS_STYLES_BY_COLOR_ID: Vec<String> =
         serde_json::from_str(include_str!("../vendor/xterm-colors.json")).unwrap();
@@ -67,6 +103,22 @@ impl Theme {
         Ok(serde_json::from_str(&json).unwrap_or_default())
     }
 
+    /// Parse a theme given either as a path to a JSON file or as the JSON itself.
+    pub fn from_path_or_json(theme: &str) -> Result<Self> {
+        let json = if theme.trim_start().starts_with(['{', '[']) {
+            theme.to_string()
+        } else {
+            fs::read_to_string(theme).with_context(|| format!("Failed to read theme {theme:?}"))?
+        };
+        let value = serde_json::from_str::<Value>(&json).context("Invalid theme JSON")?;
+        if !value.is_object() {
+            return Err(anyhow!(
+                "Invalid theme JSON: expected an object mapping highlight names to styles"
+            ));
+        }
+        Ok(serde_json::from_value(value)?)
+    }
+
     #[must_use]
     pub fn default_style(&self) -> Style {
         Style::default()
@@ -336,10 +388,13 @@ pub fn ansi(
     print_time: bool,
     cancellation_flag: Option<&AtomicUsize>,
 ) -> Result<()> {
+    use std::io::Write;
+
     let stdout = io::stdout();
     let mut stdout = stdout.lock();
     let time = Instant::now();
     let mut highlighter = Highlighter::new();
+    let color = util::color_enabled();
 
     let events = highlighter.highlight(config, source, cancellation_flag, |string| {
         loader.highlight_config_for_injection_string(string, config.apply_all_captures)
@@ -355,11 +410,15 @@ pub fn ansi(
                 style_stack.pop();
             }
             HighlightEvent::Source { start, end } => {
-                style_stack
-                    .last()
-                    .unwrap()
-                    .paint(&source[start..end])
-                    .write_to(&mut stdout)?;
+                if color {
+                    style_stack
+                        .last()
+                        .unwrap()
+                        .paint(&source[start..end])
+                        .write_to(&mut stdout)?;
+                } else {
+                    stdout.write_all(&source[start..end])?;
+                }
             }
         }
     }
@@ -371,13 +430,22 @@ pub fn ansi(
     Ok(())
 }
 
-pub fn html(
+/// Print the highlighted spans of the source as a line of JSON, together with
+/// the names of the captures that apply to each span, from outermost to innermost,
+/// and the name of the language that produced the innermost capture, which is
+/// the injected language for spans inside of injections.
+///
+/// Nested nodes contribute one capture each. When several patterns capture the
+/// same node, only one of them is used: the first pattern in the query, or the
+/// last one if the configuration was built with `apply_all_captures`. This is
+/// the same capture that the HTML and ANSI output use.
+pub fn json(
     loader: &Loader,
-    theme: &Theme,
+    path: &path::Path,
     source: &[u8],
     config: &HighlightConfiguration,
-    quiet: bool,
     print_time: bool,
+    position_encoding: Option<util::PositionEncoding>,
     cancellation_flag: Option<&AtomicUsize>,
 ) -> Result<()> {
     use std::io::Write;
@@ -386,20 +454,93 @@ pub fn html(
     let mut stdout = stdout.lock();
     let time = Instant::now();
     let mut highlighter = Highlighter::new();
+    let highlight_names = loader.highlight_names();
+
+    // With `--position-encoding`, each span also has the row and column of its start
+    // and end. The spans are in order, so the lines are counted as they go by.
+    let mut row = 0;
+    let mut line_start = 0;
+    let mut counted_offset = 0;
+    let mut position = |offset: usize, encoding: util::PositionEncoding| {
+        for newline in memchr::memchr_iter(b'\n', &source[counted_offset..offset]) {
+            row += 1;
+            line_start = counted_offset + newline + 1;
+        }
+        counted_offset = offset;
+        let point = encoding.convert_point(source, offset, Point::new(row, offset - line_start));
+        json!({ "row": point.row, "column": point.column })
+    };
+
+    let events =
+        highlighter.highlight_with_languages(config, source, cancellation_flag, |string| {
+            loader.highlight_config_for_injection_string(string, config.apply_all_captures)
+        })?;
+
+    let mut capture_stack = Vec::new();
+    let mut language_stack = Vec::new();
+    let mut spans = Vec::new();
+    for event in events {
+        match event? {
+            (HighlightEvent::HighlightStart(highlight), language_name) => {
+                capture_stack.push(highlight_names[highlight.0].as_str());
+                language_stack.push(language_name);
+            }
+            (HighlightEvent::HighlightEnd, _) => {
+                capture_stack.pop();
+                language_stack.pop();
+            }
+            (HighlightEvent::Source { start, end }, _) => {
+                if let Some(language_name) = language_stack.last() {
+                    let mut span = json!({
+                        "start": start,
+                        "end": end,
+                        "captures": capture_stack,
+                        "language": language_name,
+                    });
+                    if let Some(encoding) = position_encoding {
+                        span["start_position"] = position(start, encoding);
+                        span["end_position"] = position(end, encoding);
+                    }
+                    spans.push(span);
+                }
+            }
+        }
+    }
 
-    let events = highlighter.highlight(config, source, cancellation_flag, |string| {
-        loader.highlight_config_for_injection_string(string, config.apply_all_captures)
-    })?;
+    serde_json::to_writer(
+        &mut stdout,
+        &json!({ "path": path.to_string_lossy(), "spans": spans }),
+    )?;
+    writeln!(&mut stdout)?;
 
-    let mut renderer = HtmlRenderer::new();
-    renderer.render(events, source, &move |highlight| {
-        theme.styles[highlight.0]
-            .css
-            .as_ref()
-            .map_or_else(|| "".as_bytes(), |css_style| css_style.as_bytes())
-    })?;
+    if print_time {
+        eprintln!("Time: {}ms", time.elapsed().as_millis());
+    }
+
+    Ok(())
+}
+
+pub struct HtmlOptions {
+    pub quiet: bool,
+    pub print_time: bool,
+}
+
+pub fn html(
+    loader: &Loader,
+    theme: &Theme,
+    source: &[u8],
+    config: &HighlightConfiguration,
+    opts: &HtmlOptions,
+    cancellation_flag: Option<&AtomicUsize>,
+) -> Result<()> {
+    use std::io::Write;
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let time = Instant::now();
+    let renderer = render_html(loader, theme, source, config, cancellation_flag)?;
 
-    if !quiet {
+    if !opts.quiet {
         writeln!(&mut stdout, "<table>")?;
         for (i, line) in renderer.lines().enumerate() {
             writeln!(
@@ -412,13 +553,118 @@ pub fn html(
         writeln!(&mut stdout, "</table>")?;
     }
 
-    if print_time {
+    if opts.print_time {
+        eprintln!("Time: {}ms", time.elapsed().as_millis());
+    }
+
+    Ok(())
+}
+
+/// Like [`html`], but with the lines that differ from `old_source` marked as added,
+/// and the removed lines of `old_source` shown in between. Both sources are
+/// highlighted as a whole before they are compared, so that injections and
+/// multi-line constructs are highlighted as they would be on their own.
+pub fn html_diff(
+    loader: &Loader,
+    theme: &Theme,
+    old_source: &[u8],
+    source: &[u8],
+    config: &HighlightConfiguration,
+    opts: &HtmlOptions,
+    cancellation_flag: Option<&AtomicUsize>,
+) -> Result<()> {
+    use std::io::Write;
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let time = Instant::now();
+    let old_renderer = render_html(loader, theme, old_source, config, cancellation_flag)?;
+    let renderer = render_html(loader, theme, source, config, cancellation_flag)?;
+    let old_lines = old_renderer.lines().collect::<Vec<_>>();
+    let lines = renderer.lines().collect::<Vec<_>>();
+
+    let old_text = String::from_utf8_lossy(old_source);
+    let text = String::from_utf8_lossy(source);
+    let changeset = Changeset::new(
+        old_text.strip_suffix('\n').unwrap_or(&old_text),
+        text.strip_suffix('\n').unwrap_or(&text),
+        "\n",
+    );
+
+    if !opts.quiet {
+        let (mut old_row, mut row) = (0, 0);
+        writeln!(&mut stdout, "<table>")?;
+        for diff in &changeset.diffs {
+            match diff {
+                Difference::Same(part) => {
+                    for _ in part.split('\n') {
+                        writeln!(
+                            &mut stdout,
+                            "<tr><td class=line-number>{}</td><td class=line-number>{}</td><td class=line>{}</td></tr>",
+                            old_row + 1,
+                            row + 1,
+                            lines.get(row).copied().unwrap_or_default(),
+                        )?;
+                        old_row += 1;
+                        row += 1;
+                    }
+                }
+                Difference::Rem(part) => {
+                    for _ in part.split('\n') {
+                        writeln!(
+                            &mut stdout,
+                            "<tr class=diff-removed><td class=line-number>{}</td><td class=line-number></td><td class=line>{}</td></tr>",
+                            old_row + 1,
+                            old_lines.get(old_row).copied().unwrap_or_default(),
+                        )?;
+                        old_row += 1;
+                    }
+                }
+                Difference::Add(part) => {
+                    for _ in part.split('\n') {
+                        writeln!(
+                            &mut stdout,
+                            "<tr class=diff-added><td class=line-number></td><td class=line-number>{}</td><td class=line>{}</td></tr>",
+                            row + 1,
+                            lines.get(row).copied().unwrap_or_default(),
+                        )?;
+                        row += 1;
+                    }
+                }
+            }
+        }
+        writeln!(&mut stdout, "</table>")?;
+    }
+
+    if opts.print_time {
         eprintln!("Time: {}ms", time.elapsed().as_millis());
     }
 
     Ok(())
 }
 
+fn render_html(
+    loader: &Loader,
+    theme: &Theme,
+    source: &[u8],
+    config: &HighlightConfiguration,
+    cancellation_flag: Option<&AtomicUsize>,
+) -> Result<HtmlRenderer> {
+    let mut highlighter = Highlighter::new();
+    let events = highlighter.highlight(config, source, cancellation_flag, |string| {
+        loader.highlight_config_for_injection_string(string, config.apply_all_captures)
+    })?;
+
+    let mut renderer = HtmlRenderer::new();
+    renderer.render(events, source, &move |highlight| {
+        theme.styles[highlight.0]
+            .css
+            .as_ref()
+            .map_or_else(|| "".as_bytes(), |css_style| css_style.as_bytes())
+    })?;
+    Ok(renderer)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -427,6 +673,18 @@ mod tests {
     const JUNGLE_GREEN: &str = "#26A69A";
     const DARK_CYAN: &str = "#00AF87";
 
+    #[test]
+    fn test_html_header_with_font() {
+        let header = html_header_with_font("'Fira Code', \"DejaVu Sans Mono\", monospace").unwrap();
+        assert!(header.contains("font-family: 'Fira Code', \"DejaVu Sans Mono\", monospace"));
+
+        assert!(html_header_with_font("").is_err());
+        assert!(html_header_with_font("monospace,").is_err());
+        assert!(html_header_with_font("'Fira Code").is_err());
+        assert!(html_header_with_font("monospace; } body { color: red").is_err());
+        assert!(html_header_with_font("</style><script>").is_err());
+    }
+
     #[test]
     fn test_parse_style() {
         let original_environment_variable = env::var("COLORTERM");
@@ -1,21 +1,29 @@
 use anyhow::{anyhow, Context, Error, Result};
-use clap::{App, AppSettings, Arg, SubCommand};
+use clap::{App, AppSettings, Arg, ArgGroup, ArgMatches, SubCommand};
 use glob::glob;
 use regex::Regex;
-use std::collections::HashSet;
-use std::path::{Path, PathBuf};
-use std::{env, fs, u64};
-use tree_sitter::{ffi, Parser, Point};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashSet};
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant, SystemTime};
+use std::{env, fs, thread, u64};
+use tree_sitter::{ffi, Language, Parser, Point};
 use tree_sitter_cli::test::TestOptions;
 use tree_sitter_cli::{
+    bench::{self, BenchOptions},
     generate, highlight, logger,
     parse::{self, ParseFileOptions, ParseOutput},
-    playground, query, tags, test, test_highlight, test_tags, util, wasm,
+    playground,
+    query::{self, QueryFileOptions},
+    tags, test, test_highlight, test_tags, util, wasm,
 };
 use tree_sitter_config::Config;
 use tree_sitter_highlight::Highlighter;
 use tree_sitter_loader as loader;
 use tree_sitter_tags::TagsContext;
+use walkdir::WalkDir;
 
 const BUILD_VERSION: &str = env!("CARGO_PKG_VERSION");
 const BUILD_SHA: Option<&'static str> = option_env!("BUILD_SHA");
@@ -49,15 +57,32 @@ fn run() -> Result<()> {
         .short("d");
 
     let debug_graph_arg = Arg::with_name("debug-graph")
-        .help("Produce the log.html file with debug graphs")
+        .help("Produce an HTML file with debug graphs, at the given path if one is passed with `=`, where {basename} is replaced with the input's file name (default: log.html)")
         .long("debug-graph")
-        .short("D");
+        .short("D")
+        .takes_value(true)
+        .min_values(0)
+        .require_equals(true)
+        .value_name("path");
 
     let debug_build_arg = Arg::with_name("debug-build")
         .help("Compile a parser in debug mode")
         .long("debug-build")
         .short("0");
 
+    let position_encoding_arg = Arg::with_name("position-encoding")
+        .help("Measure the columns of positions in utf8 (bytes, the default), utf16 or utf32 code units")
+        .long("position-encoding")
+        .value_name("encoding")
+        .takes_value(true)
+        .possible_values(&["utf8", "utf16", "utf32"]);
+
+    let static_arg = Arg::with_name("static")
+        .help(
+            "Compile the parser into a static library instead of a dynamic one, and print its path",
+        )
+        .long("static");
+
     let paths_file_arg = Arg::with_name("paths-file")
         .help("The path to a file with paths to source file(s)")
         .long("paths")
@@ -67,8 +92,45 @@ fn run() -> Result<()> {
         .help("The source file(s) to use")
         .multiple(true);
 
+    let progress_arg = Arg::with_name("progress")
+        .help("Show how many of the files have been processed")
+        .long("progress");
+
+    let respect_gitignore_arg = Arg::with_name("respect-gitignore")
+        .help("Skip glob matches that are ignored by git")
+        .long("respect-gitignore");
+
+    let max_bytes_arg = Arg::with_name("max-bytes")
+        .help("Skip the files that are larger than this many bytes, with a warning")
+        .long("max-bytes")
+        .value_name("N")
+        .takes_value(true);
+
+    let fail_on_skip_arg = Arg::with_name("fail-on-skip")
+        .help("Exit with an error if any files were skipped for being too large")
+        .long("fail-on-skip")
+        .requires("max-bytes");
+
+    let newer_than_arg = Arg::with_name("newer-than")
+        .help("Only include the files that were modified after the given file")
+        .long("newer-than")
+        .value_name("path")
+        .takes_value(true)
+        .conflicts_with("modified-since");
+
+    let modified_since_arg = Arg::with_name("modified-since")
+        .help("Only include the files that were modified at or after the given time, in seconds since the Unix epoch")
+        .long("modified-since")
+        .value_name("timestamp")
+        .takes_value(true);
+
+    let recursive_arg = Arg::with_name("recursive")
+        .help("Include all of the files inside of directories that are given as paths")
+        .long("recursive")
+        .short("r");
+
     let scope_arg = Arg::with_name("scope")
-        .help("Select a language by the scope instead of a file extension")
+        .help("Select a language by its scope or name instead of a file extension")
         .long("scope")
         .takes_value(true);
 
@@ -77,6 +139,10 @@ fn run() -> Result<()> {
         .long("time")
         .short("t");
 
+    let time_json_arg = Arg::with_name("time-json")
+        .help("Print the time spent on each file to stderr, as a line of JSON")
+        .long("time-json");
+
     let quiet_arg = Arg::with_name("quiet")
         .help("Suppress main output")
         .long("quiet")
@@ -85,6 +151,12 @@ fn run() -> Result<()> {
     let wasm_arg = Arg::with_name("wasm")
         .long("wasm")
         .help("compile parsers to wasm instead of native dynamic libraries");
+    let wasm_fuel_arg = Arg::with_name("wasm-fuel")
+        .long("wasm-fuel")
+        .takes_value(true)
+        .value_name("fuel")
+        .requires("wasm")
+        .help("Limit the fuel that wasm parsers can consume in each parse");
     let apply_all_captures_arg = Arg::with_name("apply-all-captures")
         .help("Apply all captures to highlights")
         .long("apply-all-captures");
@@ -97,7 +169,82 @@ fn run() -> Result<()> {
         .global_setting(AppSettings::ColoredHelp)
         .global_setting(AppSettings::DeriveDisplayOrder)
         .global_setting(AppSettings::DisableHelpSubcommand)
-        .subcommand(SubCommand::with_name("init-config").about("Generate a default config file"))
+        .arg(
+            Arg::with_name("verbose")
+                .help("Print which language is selected for each file")
+                .long("verbose")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("cc")
+                .help("The C compiler used to build parsers (default: $CC)")
+                .long("cc")
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("scanner")
+                .help("Compile the grammar in the current directory, or the language given with --scope, with this external scanner instead of the one in its src directory")
+                .long("scanner")
+                .value_name("path")
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("cflags")
+                .help("Extra flags passed to the C compiler when building parsers (default: $CFLAGS)")
+                .long("cflags")
+                .takes_value(true)
+                .allow_hyphen_values(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("build-jobs")
+                .help("The maximum number of compiler processes to run at once (default: the number of CPUs)")
+                .long("build-jobs")
+                .value_name("N")
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("relative-paths")
+                .help("Print the paths of input files relative to the current directory")
+                .long("relative-paths")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("profile")
+                .help("Load the config file config.<name>.json, from the directory of the usual config file, instead of config.json")
+                .long("profile")
+                .value_name("name")
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("color")
+                .help("When to color the output: auto, always or never (default: auto, which respects $NO_COLOR)")
+                .long("color")
+                .value_name("when")
+                .takes_value(true)
+                .possible_values(&["auto", "always", "never"])
+                .global(true),
+        )
+        .subcommand(
+            SubCommand::with_name("init-config")
+                .about("Generate a default config file")
+                .arg(
+                    Arg::with_name("force")
+                        .help("Overwrite an existing config file, after backing it up to a file with a .bak extension")
+                        .long("force")
+                        .short("f"),
+                )
+                .arg(
+                    Arg::with_name("print")
+                        .help("Print the default config to stdout instead of writing it to a file")
+                        .long("print")
+                        .conflicts_with("force"),
+                ),
+        )
         .subcommand(
             SubCommand::with_name("generate")
                 .alias("gen")
@@ -125,6 +272,7 @@ fn run() -> Result<()> {
                         .short("b")
                         .help("Compile all defined languages in the current dir"),
                 )
+                .arg(static_arg.clone().requires("build"))
                 .arg(&debug_build_arg)
                 .arg(
                     Arg::with_name("libdir")
@@ -132,6 +280,22 @@ fn run() -> Result<()> {
                         .takes_value(true)
                         .value_name("path"),
                 )
+                .arg(
+                    Arg::with_name("stats")
+                        .long("stats")
+                        .help("Print the sizes of the generated parse tables"),
+                )
+                .arg(
+                    Arg::with_name("werror")
+                        .help("Fail if any warnings were printed while processing the grammar")
+                        .long("werror"),
+                )
+                .arg(
+                    Arg::with_name("report-states")
+                        .help("Print the number of parse states that each rule appears in")
+                        .long("report-states")
+                        .conflicts_with("report-states-for-rule"),
+                )
                 .arg(
                     Arg::with_name("report-states-for-rule")
                         .long("report-states-for-rule")
@@ -147,25 +311,136 @@ fn run() -> Result<()> {
                         .help("Use a JavaScript runtime other than node"),
                 ),
         )
+        .subcommand(
+            SubCommand::with_name("inspect")
+                .about("Print the symbols and parse states of a grammar without generating a parser")
+                .arg(Arg::with_name("grammar-path").index(1))
+                .arg(
+                    Arg::with_name("rule")
+                        .long("rule")
+                        .value_name("rule-name")
+                        .takes_value(true)
+                        .help("Report the parse states for the given rule, or for all rules with '*'"),
+                )
+                .arg(
+                    Arg::with_name("js-runtime")
+                        .long("js-runtime")
+                        .takes_value(true)
+                        .value_name("executable")
+                        .env("TREE_SITTER_JS_RUNTIME")
+                        .help("Use a JavaScript runtime other than node"),
+                ),
+        )
         .subcommand(
             SubCommand::with_name("parse")
                 .alias("p")
                 .about("Parse files")
                 .arg(&paths_file_arg)
                 .arg(&paths_arg)
-                .arg(&scope_arg)
+                .arg(&respect_gitignore_arg)
+                .arg(&recursive_arg)
+                .arg(&newer_than_arg)
+                .arg(&modified_since_arg)
+                .arg(&progress_arg)
+                .arg(
+                    scope_arg
+                        .clone()
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help(
+                            "Select a language by its scope or name instead of a file extension. \
+                             If given more than once, use the scope whose language \
+                             produces the fewest errors",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("all-scopes")
+                        .help("Parse with the language of every given scope")
+                        .long("all-scopes")
+                        .requires("scope"),
+                )
+                .arg(
+                    Arg::with_name("skip-unknown")
+                        .help("Skip the files whose names don't match the file types of any known language")
+                        .long("skip-unknown")
+                        .conflicts_with("scope"),
+                )
+                .arg(&max_bytes_arg)
+                .arg(&fail_on_skip_arg)
+                .arg(
+                    Arg::with_name("show-language-info")
+                        .help("Print the name, ABI version and node type count of each selected language to stderr")
+                        .long("show-language-info"),
+                )
                 .arg(&debug_arg)
                 .arg(&debug_build_arg)
                 .arg(&debug_graph_arg)
                 .arg(&wasm_arg)
-                .arg(Arg::with_name("output-dot").long("dot"))
-                .arg(Arg::with_name("output-xml").long("xml").short("x"))
+                .arg(&wasm_fuel_arg)
+                .arg(
+                    Arg::with_name("format")
+                        .help("The format of the syntax tree output (default: sexp)")
+                        .long("format")
+                        .takes_value(true)
+                        .possible_values(&["sexp", "dot", "xml", "json", "cst"])
+                        .conflicts_with_all(&["output-dot", "output-xml"]),
+                )
+                .arg(
+                    Arg::with_name("output-dot")
+                        .help("[DEPRECATED in favor of --format dot]")
+                        .long("dot"),
+                )
+                .arg(
+                    Arg::with_name("output-xml")
+                        .help("[DEPRECATED in favor of --format xml]")
+                        .long("xml")
+                        .short("x"),
+                )
+                .arg(
+                    Arg::with_name("dump-ast")
+                        .help("Output the named nodes as JSON, with their fields, ranges and flags")
+                        .long("dump-ast")
+                        .conflicts_with_all(&["format", "output-dot", "output-xml"]),
+                )
+                .arg(
+                    Arg::with_name("errors")
+                        .help("Show the source of each parse error, with a caret pointing at it")
+                        .long("errors"),
+                )
+                .arg(
+                    Arg::with_name("emit-schema")
+                        .help(
+                            "Output the node types that occur in the files, and the types of \
+                             their children, in the format of node-types.json",
+                        )
+                        .long("emit-schema")
+                        .conflicts_with_all(&[
+                            "format",
+                            "dump-ast",
+                            "output-dot",
+                            "output-xml",
+                            "errors",
+                            "time",
+                        ]),
+                )
+                .arg(
+                    Arg::with_name("keep-going")
+                        .help("Warn about files that can't be parsed and continue with the rest")
+                        .long("keep-going"),
+                )
                 .arg(
                     Arg::with_name("stat")
                         .help("Show parsing statistic")
                         .long("stat")
                         .short("s"),
                 )
+                .arg(
+                    Arg::with_name("stat-csv")
+                        .help("Append the parsing statistics to a CSV file, with a row for each file if --time is passed")
+                        .long("stat-csv")
+                        .takes_value(true)
+                        .value_name("path"),
+                )
                 .arg(
                     Arg::with_name("timeout")
                         .help("Interrupt the parsing process by timeout (µs)")
@@ -173,6 +448,7 @@ fn run() -> Result<()> {
                         .takes_value(true),
                 )
                 .arg(&time_arg)
+                .arg(&time_json_arg)
                 .arg(&quiet_arg)
                 .arg(
                     Arg::with_name("edits")
@@ -183,12 +459,143 @@ fn run() -> Result<()> {
                         .multiple(true)
                         .number_of_values(1),
                 )
+                .arg(
+                    Arg::with_name("diff")
+                        .help("Apply the changes in a unified diff of the file as edits")
+                        .long("diff")
+                        .takes_value(true)
+                        .value_name("path"),
+                )
+                .group(
+                    ArgGroup::with_name("edit-sources")
+                        .args(&["edits", "diff"])
+                        .multiple(true),
+                )
+                .arg(
+                    Arg::with_name("reparse-bench")
+                        .help("Compare the incremental reparse time after the edits with a full parse")
+                        .long("reparse-bench")
+                        .requires("edit-sources"),
+                )
+                .arg(
+                    Arg::with_name("no-ranges")
+                        .help("Omit the positions of the nodes from the syntax tree")
+                        .long("no-ranges"),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .help("Write the syntax trees to this file instead of stdout, with a comment naming each file if there are several")
+                        .long("output")
+                        .short("o")
+                        .value_name("path")
+                        .takes_value(true)
+                        .conflicts_with_all(&["quiet", "output-dot", "emit-schema"]),
+                )
+                .arg(
+                    Arg::with_name("save-tree")
+                        .help("Save the syntax trees to this file, in a binary format that --load-tree can print without parsing again")
+                        .long("save-tree")
+                        .value_name("path")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("load-tree")
+                        .help("Print the syntax trees saved by --save-tree instead of parsing any files. The saved trees can't be edited and reparsed.")
+                        .long("load-tree")
+                        .value_name("path")
+                        .takes_value(true)
+                        .conflicts_with_all(&[
+                            "paths",
+                            "paths-file",
+                            "save-tree",
+                            "edit-sources",
+                            "format",
+                            "dump-ast",
+                            "output-dot",
+                            "output-xml",
+                            "emit-schema",
+                        ]),
+                )
+                .arg(
+                    Arg::with_name("compact")
+                        .help("Print each tree as an S-expression on a single line")
+                        .long("compact")
+                        .alias("sexp")
+                        .conflicts_with_all(&["format", "dump-ast", "output-dot", "output-xml", "quiet"]),
+                )
+                .arg(
+                    Arg::with_name("drop-extras")
+                        .help("Omit extras, like comments, from the syntax tree")
+                        .long("drop-extras")
+                        .alias("strip-comments")
+                        .conflicts_with("output-dot"),
+                )
+                .arg(
+                    Arg::with_name("filter-node")
+                        .help("Only print the subtrees that are rooted at nodes of the given type")
+                        .long("filter-node")
+                        .value_name("type")
+                        .takes_value(true)
+                        .conflicts_with_all(&["format", "dump-ast", "output-dot", "output-xml", "quiet"]),
+                )
+                .arg(
+                    Arg::with_name("max-nesting")
+                        .help("Print the depth of the most deeply nested node in each tree")
+                        .long("max-nesting"),
+                )
+                .arg(
+                    Arg::with_name("max-nesting-limit")
+                        .help("Print the depth of each tree, and fail if any tree is nested more deeply than this")
+                        .long("max-nesting-limit")
+                        .value_name("depth")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("count-nodes")
+                        .help("Print the number of nodes, the number of named nodes and the depth of each tree, and their totals with --stat")
+                        .long("count-nodes"),
+                )
+                .arg(
+                    Arg::with_name("error-cost")
+                        .help("Print the total cost of the syntax errors in all of the files, for comparing grammars (lower is better)")
+                        .long("error-cost")
+                        .conflicts_with("emit-schema"),
+                )
+                .arg(
+                    Arg::with_name("named-only")
+                        .help("Leave anonymous nodes out of the cst and json formats too, like the S-expression output")
+                        .long("named-only")
+                        .conflicts_with_all(&["dump-ast", "output-dot", "output-xml"]),
+                )
+                .arg(
+                    Arg::with_name("deadline-ms")
+                        .help("Stop parsing files once this many milliseconds have passed in total")
+                        .long("deadline-ms")
+                        .value_name("ms")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("retries")
+                        .help("Parse a file up to this many more times if parsing it fails")
+                        .long("retries")
+                        .value_name("N")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("byte-range")
+                        .help("The range of byte offsets that will be parsed")
+                        .long("byte-range")
+                        .takes_value(true),
+                )
                 .arg(
                     Arg::with_name("encoding")
                         .help("The encoding of the input files")
                         .long("encoding")
                         .takes_value(true),
-                ),
+                )
+                .arg(position_encoding_arg.clone().help(
+                    "Measure the columns of positions in the JSON outputs (--format json or --dump-ast) in utf8 (bytes, the default), utf16 or utf32 code units",
+                )),
         )
         .subcommand(
             SubCommand::with_name("query")
@@ -196,14 +603,39 @@ fn run() -> Result<()> {
                 .about("Search files using a syntax tree query")
                 .arg(
                     Arg::with_name("query-path")
-                        .help("Path to a file with queries")
+                        .help("Path to a file with queries, or - to read them from stdin")
                         .index(1)
-                        .required(true),
+                        .required_unless("query-name"),
+                )
+                .arg(
+                    Arg::with_name("query-name")
+                        .help("Use the language's own queries with the given name, e.g. highlights, injections, locals or tags, instead of a query file")
+                        .long("query-name")
+                        .takes_value(true)
+                        .value_name("name")
+                        .conflicts_with("validate"),
                 )
                 .arg(&time_arg)
+                .arg(&time_json_arg)
                 .arg(&quiet_arg)
                 .arg(&paths_file_arg)
                 .arg(&paths_arg.clone().index(2))
+                .arg(
+                    Arg::with_name("include")
+                        .long("include")
+                        .takes_value(true)
+                        .help("Only query the files whose path matches the given regex"),
+                )
+                .arg(
+                    Arg::with_name("exclude")
+                        .long("exclude")
+                        .takes_value(true)
+                        .help("Only query the files whose path does not match the given regex"),
+                )
+                .arg(&respect_gitignore_arg)
+                .arg(&recursive_arg)
+                .arg(&newer_than_arg)
+                .arg(&modified_since_arg)
                 .arg(
                     Arg::with_name("byte-range")
                         .help("The range of byte offsets in which the query will be executed")
@@ -218,16 +650,142 @@ fn run() -> Result<()> {
                 )
                 .arg(&scope_arg)
                 .arg(Arg::with_name("captures").long("captures").short("c"))
-                .arg(Arg::with_name("test").long("test")),
+                .arg(Arg::with_name("test").long("test"))
+                .arg(
+                    Arg::with_name("output-dot")
+                        .help("Output the nodes of each match as a DOT graph")
+                        .long("dot"),
+                )
+                .arg(
+                    Arg::with_name("context")
+                        .help("Print the given number of source lines around each match")
+                        .long("context")
+                        .short("C")
+                        .takes_value(true)
+                        .value_name("lines"),
+                )
+                .arg(
+                    Arg::with_name("any")
+                        .help("Stop at the first match in any file and print its location, exiting with an error if there is none")
+                        .long("any")
+                        .alias("first-match-only")
+                        .conflicts_with_all(&["captures", "output-dot", "capture-text", "test"]),
+                )
+                .arg(
+                    Arg::with_name("named-only")
+                        .help("Only print the captures of named nodes")
+                        .long("named-only")
+                        .alias("only-named"),
+                )
+                .arg(
+                    Arg::with_name("limit")
+                        .help("Stop after the given number of matches in each file")
+                        .long("limit")
+                        .takes_value(true)
+                        .value_name("count"),
+                )
+                .arg(
+                    Arg::with_name("limit-total")
+                        .help("Stop after the given number of matches across all files")
+                        .long("limit-total")
+                        .takes_value(true)
+                        .value_name("count"),
+                )
+                .arg(
+                    Arg::with_name("validate")
+                        .help("Only check that the query compiles, without running it on any files. The language comes from --scope or the grammar in the current directory")
+                        .long("validate"),
+                )
+                .arg(
+                    Arg::with_name("capture-text")
+                        .help("Print only the source text of the given capture, one per line")
+                        .long("capture-text")
+                        .takes_value(true)
+                        .value_name("capture-name")
+                        .conflicts_with_all(&["captures", "output-dot"]),
+                )
+                .arg(
+                    Arg::with_name("xml")
+                        .help("Output the matches as XML, with the capture names of the nodes as attributes")
+                        .long("xml")
+                        .short("x")
+                        .conflicts_with_all(&["captures", "output-dot", "capture-text", "any", "test", "group-by"]),
+                )
+                .arg(
+                    Arg::with_name("group-by")
+                        .help("Count the matches by the source text of the given capture, and print the counts, most frequent first")
+                        .long("group-by")
+                        .takes_value(true)
+                        .value_name("capture-name")
+                        .conflicts_with_all(&["captures", "output-dot", "capture-text", "any", "test"]),
+                )
+                .arg(
+                    Arg::with_name("properties")
+                        .help("Print the #set! properties, the #is? and #is-not? assertions and the other predicates of each match's pattern")
+                        .long("properties")
+                        .conflicts_with_all(&["captures", "output-dot", "capture-text", "any", "xml", "group-by"]),
+                )
+                .arg(
+                    Arg::with_name("report-unmatched")
+                        .help("After querying all of the files, list the patterns that never matched, with their source text")
+                        .long("report-unmatched")
+                        .conflicts_with("any"),
+                )
+                .arg(
+                    Arg::with_name("sort")
+                        .help("Print the files in order of their paths, and the matches in each file in order of their positions, instead of as they are found")
+                        .long("sort")
+                        .conflicts_with_all(&["any", "group-by"]),
+                )
+                .arg(&position_encoding_arg)
+                .arg(
+                    Arg::with_name("max-start-depth")
+                        .help("The maximum depth at which the query's matches may start")
+                        .long("max-start-depth")
+                        .takes_value(true),
+                ),
         )
         .subcommand(
             SubCommand::with_name("tags")
                 .about("Generate a list of tags")
                 .arg(&scope_arg)
                 .arg(&time_arg)
+                .arg(&time_json_arg)
                 .arg(&quiet_arg)
                 .arg(&paths_file_arg)
-                .arg(&paths_arg),
+                .arg(&paths_arg)
+                .arg(&respect_gitignore_arg)
+                .arg(&recursive_arg)
+                .arg(&newer_than_arg)
+                .arg(&modified_since_arg)
+                .arg(
+                    Arg::with_name("sort")
+                        .help("The order in which to list each file's tags")
+                        .long("sort")
+                        .takes_value(true)
+                        .possible_values(&["name", "kind", "position"])
+                        .default_value("position"),
+                )
+                .arg(
+                    Arg::with_name("tags-query")
+                        .help("Use the concatenation of these query files instead of the language's tags query")
+                        .long("tags-query")
+                        .value_name("path")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1),
+                )
+                .arg(
+                    Arg::with_name("include-extras")
+                        .help("List the tags whose nodes are inside of extras, like comments (the default)")
+                        .long("include-extras"),
+                )
+                .arg(
+                    Arg::with_name("exclude-extras")
+                        .help("Leave out the tags whose nodes are inside of extras, like comments")
+                        .long("exclude-extras")
+                        .overrides_with("include-extras"),
+                ),
         )
         .subcommand(
             SubCommand::with_name("test")
@@ -262,29 +820,161 @@ fn run() -> Result<()> {
                         .short("u")
                         .help("Update all syntax trees in corpus files with current parser output"),
                 )
+                .arg(
+                    Arg::with_name("dry-run")
+                        .long("dry-run")
+                        .requires("update")
+                        .help("Show how the syntax trees would be updated, without changing the corpus files"),
+                )
+                .arg(
+                    Arg::with_name("normalize-whitespace")
+                        .long("normalize-whitespace")
+                        .help("Ignore differences in whitespace when comparing syntax trees"),
+                )
+                .arg(
+                    Arg::with_name("parse-only")
+                        .long("parse-only")
+                        .conflicts_with("update")
+                        .help("Only check that the corpus inputs parse without errors, ignoring the expected syntax trees"),
+                )
+                .arg(
+                    Arg::with_name("failures-only")
+                        .long("failures-only")
+                        .help("Only print the tests that fail, along with the groups that contain them"),
+                )
+                .arg(
+                    Arg::with_name("shuffle")
+                        .long("shuffle")
+                        .conflicts_with("update")
+                        .help("Run the tests in a random order, and print the seed that was used"),
+                )
+                .arg(
+                    Arg::with_name("seed")
+                        .long("seed")
+                        .value_name("N")
+                        .takes_value(true)
+                        .requires("shuffle")
+                        .help("Shuffle the tests with the given seed, to repeat an earlier order"),
+                )
+                .arg(
+                    Arg::with_name("coverage")
+                        .long("coverage")
+                        .help("Report the named node types that no corpus test produces"),
+                )
                 .arg(&debug_arg)
                 .arg(&debug_build_arg)
                 .arg(&debug_graph_arg)
                 .arg(&wasm_arg)
-                .arg(&apply_all_captures_arg),
+                .arg(&wasm_fuel_arg)
+                .arg(&apply_all_captures_arg)
+                .arg(
+                    Arg::with_name("show-build-warnings")
+                        .long("show-build-warnings")
+                        .help("Print any warnings emitted while compiling the parser"),
+                )
+                .arg(
+                    Arg::with_name("grammar-dir")
+                        .long("grammar-dir")
+                        .takes_value(true)
+                        .value_name("path")
+                        .help("The grammar directory to test, instead of the current directory"),
+                )
+                .arg(
+                    Arg::with_name("watch")
+                        .long("watch")
+                        .help("Run the tests again whenever the grammar, corpus or queries change"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("bench")
+                .about("Measure how quickly the parser in the current directory parses some files")
+                .arg(&paths_file_arg)
+                .arg(&paths_arg)
+                .arg(&respect_gitignore_arg)
+                .arg(&recursive_arg)
+                .arg(&newer_than_arg)
+                .arg(&modified_since_arg)
+                .arg(
+                    Arg::with_name("iterations")
+                        .help("The number of times each file is parsed and timed")
+                        .long("iterations")
+                        .short("n")
+                        .takes_value(true)
+                        .default_value("10"),
+                )
+                .arg(
+                    Arg::with_name("warmup")
+                        .help("The number of times each file is parsed before timing it")
+                        .long("warmup")
+                        .takes_value(true)
+                        .default_value("3"),
+                )
+                .arg(
+                    Arg::with_name("baseline")
+                        .help("Compare the results with this file if it exists, or save them to it")
+                        .long("baseline")
+                        .takes_value(true)
+                        .value_name("path"),
+                )
+                .arg(&debug_build_arg),
         )
         .subcommand(
             SubCommand::with_name("highlight")
                 .about("Highlight a file")
+                .arg(
+                    Arg::with_name("font")
+                        .help("The CSS font family of the HTML output, like 'Fira Code, monospace'")
+                        .long("font")
+                        .takes_value(true)
+                        .requires("html"),
+                )
+                .arg(
+                    Arg::with_name("theme")
+                        .help("The theme to use instead of the configured one, as a path to a JSON file or as JSON")
+                        .long("theme")
+                        .takes_value(true)
+                        .env("TREE_SITTER_THEME"),
+                )
                 .arg(
                     Arg::with_name("html")
                         .help("Generate highlighting as an HTML document")
                         .long("html")
                         .short("H"),
                 )
+                .arg(
+                    Arg::with_name("json")
+                        .help("Output the highlighted spans and their captures as JSON")
+                        .long("json")
+                        .conflicts_with("html"),
+                )
+                .arg(position_encoding_arg.clone().requires("json"))
+                .arg(
+                    Arg::with_name("force-ansi-color")
+                        .help("Keep the ANSI color codes even when the output isn't a terminal, e.g. when piping it into `less -R`")
+                        .long("force-ansi-color")
+                        .conflicts_with_all(&["html", "json", "color"]),
+                )
+                .arg(
+                    Arg::with_name("against")
+                        .help("Mark the lines that were added or removed relative to an older version of the file")
+                        .long("against")
+                        .takes_value(true)
+                        .value_name("old-path")
+                        .requires("html"),
+                )
                 .arg(
                     Arg::with_name("check")
-                        .help("Check that highlighting captures conform strictly to standards")
+                        .help("Check that highlighting captures conform strictly to the standard capture names")
                         .long("check"),
                 )
+                .arg(
+                    Arg::with_name("check-strict")
+                        .help("Like --check, but also fail if any capture doesn't match a highlight name in the theme")
+                        .long("check-strict"),
+                )
                 .arg(
                     Arg::with_name("captures-path")
-                        .help("Path to a file with captures")
+                        .help("Path to a file with the capture names to check against, instead of the standard ones")
                         .long("captures-path")
                         .takes_value(true),
                 )
@@ -298,10 +988,37 @@ fn run() -> Result<()> {
                 )
                 .arg(&scope_arg)
                 .arg(&time_arg)
+                .arg(&time_json_arg)
                 .arg(&quiet_arg)
                 .arg(&paths_file_arg)
                 .arg(&paths_arg)
-                .arg(&apply_all_captures_arg),
+                .arg(&respect_gitignore_arg)
+                .arg(&recursive_arg)
+                .arg(&newer_than_arg)
+                .arg(&modified_since_arg)
+                .arg(&max_bytes_arg)
+                .arg(&fail_on_skip_arg)
+                .arg(&progress_arg)
+                .arg(&apply_all_captures_arg)
+                .arg(
+                    Arg::with_name("watch")
+                        .help("Highlight the files again whenever they, the highlight queries or the theme change")
+                        .long("watch")
+                        .conflicts_with_all(&["check", "check-strict"]),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("build")
+                .about("Compile all defined languages in a directory, and print the paths of the libraries")
+                .arg(Arg::with_name("path").index(1))
+                .arg(&static_arg)
+                .arg(&debug_build_arg)
+                .arg(
+                    Arg::with_name("libdir")
+                        .long("libdir")
+                        .takes_value(true)
+                        .value_name("path"),
+                ),
         )
         .subcommand(
             SubCommand::with_name("build-wasm")
@@ -312,6 +1029,11 @@ fn run() -> Result<()> {
                         "Run emscripten via docker or podman even if it is installed locally",
                     ),
                 )
+                .arg(
+                    Arg::with_name("dry-run")
+                        .long("dry-run")
+                        .help("Print the command that would compile the parser, and its working directory, without running it"),
+                )
                 .arg(Arg::with_name("path").index(1).multiple(true)),
         )
         .subcommand(
@@ -328,33 +1050,58 @@ fn run() -> Result<()> {
                 ),
         )
         .subcommand(
-            SubCommand::with_name("dump-languages")
-                .about("Print info about all known language parsers"),
+            SubCommand::with_name("inspect-language")
+                .about("Print the node types and field names of a language, and the capture names used by its queries")
+                .arg(&scope_arg),
         )
-        .get_matches();
-
-    let current_dir = env::current_dir().unwrap();
-    let config = Config::load()?;
-    let mut loader = loader::Loader::new()?;
+        .subcommand(
+            SubCommand::with_name("dump-languages")
+                .about("Print info about all known language parsers")
+                .arg(
+                    Arg::with_name("check-conflicts")
+                        .long("check-conflicts")
+                        .help("Report file types and content regexes claimed by more than one language. Content regexes only conflict when they are written identically, since regexes that are written differently but match the same text aren't detected"),
+                )
+                .arg(
+                    Arg::with_name("abi")
+                        .long("abi")
+                        .help("Load each parser and print the ABI version it was generated with"),
+                )
+                .arg(
+                    Arg::with_name("abi-only")
+                        .long("abi-only")
+                        .help("Only print the parsers whose ABI version isn't supported by this version of the CLI"),
+                ),
+        )
+        .get_matches();
 
-    match matches.subcommand() {
-        ("init-config", Some(_)) => {
-            if let Ok(Some(config_path)) = Config::find_config_file() {
-                return Err(anyhow!(
-                    "Remove your existing config file first: {}",
-                    config_path.to_string_lossy()
-                ));
-            }
-            let mut config = Config::initial()?;
-            config.add(tree_sitter_loader::Config::initial())?;
-            config.add(tree_sitter_cli::highlight::ThemeConfig::default())?;
-            config.save()?;
-            println!(
-                "Saved initial configuration to {}",
-                config.location.display()
-            );
-        }
+    util::init_color(
+        matches
+            .subcommand()
+            .1
+            .and_then(|m| m.value_of("color"))
+            .or_else(|| matches.value_of("color"))
+            .unwrap_or("auto"),
+    );
+
+    let current_dir = env::current_dir().unwrap();
+    let profile = matches
+        .subcommand()
+        .1
+        .and_then(|m| m.value_of("profile"))
+        .or_else(|| matches.value_of("profile"));
+    // `init-config` creates the config file, so it can't be loaded first.
+    if let ("init-config", Some(matches)) = matches.subcommand() {
+        return init_config(matches, profile);
+    }
+    let config = match profile {
+        Some(name) => Config::load_profile(name)?,
+        None => Config::load()?,
+    };
+    let mut loader = loader::Loader::new()?;
+    configure_loader(&mut loader, &matches)?;
 
+    match matches.subcommand() {
         ("generate", Some(matches)) => {
             let grammar_path = matches.value_of("grammar-path");
             let debug_build = matches.is_present("debug-build");
@@ -391,27 +1138,62 @@ fn run() -> Result<()> {
                 generate_bindings,
                 report_symbol_name,
                 js_runtime,
+                matches.is_present("stats"),
             )?;
+            if matches.is_present("werror") && generate::warning_count() > 0 {
+                return Err(anyhow!(
+                    "Treating {} as errors, because --werror was passed",
+                    match generate::warning_count() {
+                        1 => "1 warning".to_string(),
+                        count => format!("{count} warnings"),
+                    }
+                ));
+            }
             if build {
                 if let Some(path) = libdir {
                     loader = loader::Loader::with_parser_lib_path(PathBuf::from(path));
+                    configure_loader(&mut loader, matches)?;
                 }
                 loader.use_debug_build(debug_build);
-                loader.languages_at_path(&current_dir)?;
+                if matches.is_present("static") {
+                    loader.use_static_build(true);
+                    for library_path in loader.compile_languages_at_path(&current_dir)? {
+                        println!("{}", library_path.display());
+                    }
+                } else {
+                    loader.languages_at_path(&current_dir)?;
+                }
             }
         }
 
+        ("inspect", Some(matches)) => {
+            generate::inspect_grammar_in_directory(
+                &current_dir,
+                matches.value_of("grammar-path"),
+                matches.value_of("rule"),
+                matches.value_of("js-runtime"),
+            )?;
+        }
+
         ("test", Some(matches)) => {
             let debug = matches.is_present("debug");
-            let debug_graph = matches.is_present("debug-graph");
+            let debug_graph = matches
+                .is_present("debug-graph")
+                .then(|| matches.value_of("debug-graph").unwrap_or("log.html"));
             let debug_build = matches.is_present("debug-build");
             let update = matches.is_present("update");
+            let coverage = matches.is_present("coverage");
             let filter = matches.value_of("filter");
             let include: Option<Regex> =
                 matches.value_of("include").and_then(|s| Regex::new(s).ok());
             let exclude: Option<Regex> =
                 matches.value_of("exclude").and_then(|s| Regex::new(s).ok());
             let apply_all_captures = matches.is_present("apply-all-captures");
+            let grammar_dir = current_dir.join(matches.value_of("grammar-dir").unwrap_or(""));
+
+            if matches.is_present("watch") {
+                return test::watch_tests_at_path(&grammar_dir);
+            }
 
             if debug {
                 // For augmenting debug logging in external scanners
@@ -419,32 +1201,38 @@ fn run() -> Result<()> {
             }
 
             loader.use_debug_build(debug_build);
+            loader.show_build_warnings(matches.is_present("show-build-warnings"));
 
             let mut parser = Parser::new();
 
             #[cfg(feature = "wasm")]
             if matches.is_present("wasm") {
-                let engine = tree_sitter::wasmtime::Engine::default();
-                parser
-                    .set_wasm_store(tree_sitter::WasmStore::new(engine.clone()).unwrap())
-                    .unwrap();
-                loader.use_wasm(engine);
+                configure_wasm(&mut parser, &mut loader, matches)?;
             }
 
-            let languages = loader.languages_at_path(&current_dir)?;
+            let languages = loader.languages_at_path(&grammar_dir)?;
             let language = languages
                 .first()
                 .ok_or_else(|| anyhow!("No language found"))?;
             parser.set_language(language)?;
 
-            let test_dir = current_dir.join("test");
+            let test_dir = grammar_dir.join("test");
 
             // Run the corpus tests. Look for them at two paths: `test/corpus` and `corpus`.
             let mut test_corpus_dir = test_dir.join("corpus");
             if !test_corpus_dir.is_dir() {
-                test_corpus_dir = current_dir.join("corpus");
+                test_corpus_dir = grammar_dir.join("corpus");
             }
             if test_corpus_dir.is_dir() {
+                let shuffle_seed = if matches.is_present("shuffle") {
+                    Some(matches.value_of("seed").map_or_else(
+                        || Ok(rand::random()),
+                        |seed| seed.parse::<u64>().with_context(|| "Invalid seed"),
+                    )?)
+                } else {
+                    None
+                };
+                let cancellation_flag = util::cancel_on_signal();
                 let mut opts = TestOptions {
                     path: test_corpus_dir,
                     debug,
@@ -453,13 +1241,20 @@ fn run() -> Result<()> {
                     include,
                     exclude,
                     update,
+                    dry_run: matches.is_present("dry-run"),
+                    coverage,
+                    normalize_whitespace: matches.is_present("normalize-whitespace"),
+                    parse_only: matches.is_present("parse-only"),
+                    failures_only: matches.is_present("failures-only"),
+                    shuffle_seed,
+                    cancellation_flag: Some(&cancellation_flag),
                 };
 
                 test::run_tests_at_path(&mut parser, &mut opts)?;
             }
 
             // Check that all of the queries are valid.
-            test::check_queries_at_path(language, &current_dir.join("queries"))?;
+            test::check_queries_at_path(language, &grammar_dir.join("queries"))?;
 
             // Run the syntax highlighting tests.
             let test_highlight_dir = test_dir.join("highlight");
@@ -484,15 +1279,39 @@ fn run() -> Result<()> {
         }
 
         ("parse", Some(matches)) => {
+            if let Some(path) = matches.value_of("load-tree") {
+                return parse::print_saved_trees(
+                    Path::new(path),
+                    &parse::SexpOptions {
+                        no_ranges: matches.is_present("no-ranges"),
+                        compact: matches.is_present("compact"),
+                        drop_extras: matches.is_present("drop-extras"),
+                        filter_node: matches.value_of("filter-node"),
+                    },
+                );
+            }
+
             let debug = matches.is_present("debug");
-            let debug_graph = matches.is_present("debug-graph");
+            let debug_graph = matches
+                .is_present("debug-graph")
+                .then(|| matches.value_of("debug-graph").unwrap_or("log.html"));
             let debug_build = matches.is_present("debug-build");
 
-            let output = if matches.is_present("output-dot") {
+            let output = if let Some(format) = matches.value_of("format") {
+                match format {
+                    "dot" => ParseOutput::Dot,
+                    "xml" => ParseOutput::Xml,
+                    "json" => ParseOutput::Json,
+                    "cst" => ParseOutput::Cst,
+                    _ => ParseOutput::Normal,
+                }
+            } else if matches.is_present("dump-ast") {
+                ParseOutput::Ast
+            } else if matches.is_present("output-dot") {
                 ParseOutput::Dot
             } else if matches.is_present("output-xml") {
                 ParseOutput::Xml
-            } else if matches.is_present("quiet") {
+            } else if matches.is_present("quiet") || matches.is_present("emit-schema") {
                 ParseOutput::Quiet
             } else {
                 ParseOutput::Normal
@@ -508,7 +1327,21 @@ fn run() -> Result<()> {
                         None => Ok(None),
                     })?;
 
+            // Only the JSON outputs include columns, so the encoding would be ignored by
+            // the others.
+            if matches.is_present("position-encoding")
+                && !matches!(output, ParseOutput::Json | ParseOutput::Ast)
+            {
+                return Err(anyhow!(
+                    "--position-encoding requires a JSON output: --format json or --dump-ast"
+                ));
+            }
+            let position_encoding = matches
+                .value_of("position-encoding")
+                .map_or(Ok(util::PositionEncoding::default()), str::parse)?;
+
             let time = matches.is_present("time");
+            let time_json = matches.is_present("time-json");
             let edits = matches
                 .values_of("edits")
                 .map_or(Vec::new(), std::iter::Iterator::collect);
@@ -524,69 +1357,261 @@ fn run() -> Result<()> {
 
             #[cfg(feature = "wasm")]
             if matches.is_present("wasm") {
-                let engine = tree_sitter::wasmtime::Engine::default();
-                parser
-                    .set_wasm_store(tree_sitter::WasmStore::new(engine.clone()).unwrap())
-                    .unwrap();
-                loader.use_wasm(engine);
+                configure_wasm(&mut parser, &mut loader, matches)?;
             }
 
             let timeout = matches
                 .value_of("timeout")
                 .map_or(0, |t| t.parse::<u64>().unwrap());
+            let retries = matches
+                .value_of("retries")
+                .map_or(Ok(0), str::parse::<usize>)
+                .context("Invalid number of retries")?;
+            let max_nesting_limit = matches
+                .value_of("max-nesting-limit")
+                .map(str::parse::<usize>)
+                .transpose()
+                .context("Invalid nesting limit")?;
+
+            let byte_range = matches
+                .value_of("byte-range")
+                .map(|arg| {
+                    arg.split_once(':')
+                        .and_then(|(start, end)| Some(start.parse().ok()?..end.parse().ok()?))
+                        .ok_or_else(|| anyhow!("Invalid --byte-range {arg:?}, expected start:end"))
+                })
+                .transpose()?;
+
+            let paths = filter_modified_paths(
+                collect_paths(
+                    matches.value_of("paths-file"),
+                    matches.values_of("paths"),
+                    matches.is_present("respect-gitignore"),
+                    matches.is_present("recursive"),
+                )?,
+                matches,
+            )?;
+            let mut paths = relative_paths(paths, matches, &current_dir);
+            let loader_config = config.get()?;
+            loader.find_all_languages(&loader_config)?;
 
-            let paths = collect_paths(matches.value_of("paths-file"), matches.values_of("paths"))?;
+            // With `--skip-unknown`, files are only parsed if their names match the file
+            // types of a known language, including the grammar in the current directory.
+            if matches.is_present("skip-unknown") {
+                loader
+                    .find_language_configurations_at_path(&current_dir, true)
+                    .ok();
+                let mut known_paths = Vec::new();
+                for path in paths {
+                    if loader
+                        .language_configuration_for_file_name(Path::new(&path))?
+                        .is_some()
+                    {
+                        known_paths.push(path);
+                    }
+                }
+                paths = known_paths;
+            }
 
             let max_path_length = paths.iter().map(|p| p.chars().count()).max().unwrap_or(0);
             let mut has_error = false;
-            let loader_config = config.get()?;
-            loader.find_all_languages(&loader_config)?;
-
-            let should_track_stats = matches.is_present("stat");
+            let keep_going = matches.is_present("keep-going");
+            let deadline = matches
+                .value_of("deadline-ms")
+                .map(str::parse::<u64>)
+                .transpose()
+                .context("Invalid deadline")?
+                .map(|ms| Instant::now() + Duration::from_millis(ms));
+            let scopes = matches
+                .values_of("scope")
+                .map_or_else(Vec::new, Iterator::collect::<Vec<_>>);
+            let all_scopes = matches.is_present("all-scopes");
+            let max_bytes = parse_max_bytes(matches)?;
+            let fail_on_skip = matches.is_present("fail-on-skip");
+            let show_language_info = matches.is_present("show-language-info");
+            let mut shown_languages = HashSet::new();
+            let schema = matches
+                .is_present("emit-schema")
+                .then(|| RefCell::new(parse::Schema::default()));
+            let output_file = matches
+                .value_of("output")
+                .map(|path| {
+                    fs::File::create(path)
+                        .with_context(|| format!("Failed to create output file {path:?}"))
+                        .map(|file| RefCell::new(io::BufWriter::new(file)))
+                })
+                .transpose()?;
+            let saved_tree_file = matches
+                .value_of("save-tree")
+                .map(|path| -> Result<_> {
+                    let mut file = io::BufWriter::new(
+                        fs::File::create(path)
+                            .with_context(|| format!("Failed to create tree file {path:?}"))?,
+                    );
+                    file.write_all(parse::SAVED_TREE_HEADER)?;
+                    Ok(RefCell::new(file))
+                })
+                .transpose()?;
+
+            let should_track_stats = matches.is_present("stat") || matches.is_present("stat-csv");
             let mut stats = parse::Stats::default();
-
-            for path in paths {
+            let mut stat_csv = matches
+                .value_of("stat-csv")
+                .map(|path| -> Result<_> {
+                    let mut file = fs::OpenOptions::new()
+                        .create(true)
+                        .append(true)
+                        .open(path)
+                        .with_context(|| format!("Failed to open stats file {path:?}"))?;
+                    if file.metadata()?.len() == 0 {
+                        writeln!(&mut file, "{}", parse::Stats::CSV_HEADER)?;
+                    }
+                    Ok(file)
+                })
+                .transpose()?;
+
+            let mut progress = util::Progress::new(paths.len(), matches.is_present("progress"));
+            let path_count = paths.len();
+            let mut parsed_count_at_deadline = None;
+            let mut total_error_cost = 0;
+            for (i, path) in paths.into_iter().enumerate() {
+                if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                    parsed_count_at_deadline = Some(i);
+                    break;
+                }
+                progress.tick();
                 let path = Path::new(&path);
+                if skip_large_file(path, max_bytes)? {
+                    has_error |= fail_on_skip;
+                    continue;
+                }
 
-                let language =
-                    loader.select_language(path, &current_dir, matches.value_of("scope"))?;
-                parser
-                    .set_language(&language)
-                    .context("incompatible language")?;
-
-                let opts = ParseFileOptions {
-                    language: language.clone(),
+                let languages = match select_parse_languages(
+                    &mut loader,
+                    &mut parser,
                     path,
-                    edits: &edits,
-                    max_path_length,
-                    output,
-                    print_time: time,
-                    timeout,
-                    debug,
-                    debug_graph,
-                    cancellation_flag: Some(&cancellation_flag),
-                    encoding,
+                    &current_dir,
+                    &scopes,
+                    all_scopes,
+                ) {
+                    Ok(languages) => languages,
+                    Err(error) if keep_going => {
+                        eprintln!("Warning: skipping {}: {error:#}", path.display());
+                        has_error = true;
+                        continue;
+                    }
+                    Err(error) => return Err(error),
                 };
 
-                let parse_result = parse::parse_file_at_path(&mut parser, &opts)?;
+                if show_language_info {
+                    for language in &languages {
+                        if shown_languages.insert(language.clone()) {
+                            eprintln!(
+                                "Language {}: ABI version {}, {} node types",
+                                loader.language_name(language).unwrap_or("<unknown>"),
+                                language.version(),
+                                language.node_kind_count()
+                            );
+                        }
+                    }
+                }
+
+                if let Some(output_file) = output_file.as_ref().filter(|_| path_count > 1) {
+                    parse::write_output_file_header(&mut *output_file.borrow_mut(), output, path)?;
+                }
+
+                for language in languages {
+                    parser.set_language(&language)?;
+                    let opts = ParseFileOptions {
+                        language: language.clone(),
+                        path,
+                        edits: &edits,
+                        diff: matches.value_of("diff").map(Path::new),
+                        max_path_length,
+                        output,
+                        print_time: time,
+                        timeout,
+                        debug,
+                        debug_graph,
+                        reparse_bench: matches.is_present("reparse-bench"),
+                        byte_range: byte_range.clone(),
+                        no_ranges: matches.is_present("no-ranges"),
+                        compact: matches.is_present("compact"),
+                        drop_extras: matches.is_present("drop-extras"),
+                        filter_node: matches.value_of("filter-node"),
+                        named_only: matches.is_present("named-only"),
+                        retries,
+                        print_errors: matches.is_present("errors"),
+                        schema: schema.as_ref(),
+                        output_file: output_file.as_ref(),
+                        saved_tree_file: saved_tree_file.as_ref(),
+                        cancellation_flag: Some(&cancellation_flag),
+                        encoding,
+                        position_encoding,
+                        count_nodes: matches.is_present("count-nodes"),
+                        max_nesting: matches.is_present("max-nesting")
+                            || max_nesting_limit.is_some(),
+                        max_nesting_limit,
+                    };
+
+                    let parse_result = match parse::parse_file_at_path(&mut parser, &opts) {
+                        Ok(parse_result) => parse_result,
+                        Err(error) if keep_going => {
+                            eprintln!("Warning: failed to parse {}: {error:#}", path.display());
+                            has_error = true;
+                            continue;
+                        }
+                        Err(error) => return Err(error),
+                    };
 
-                if should_track_stats {
-                    stats.total_parses += 1;
-                    if parse_result.successful {
-                        stats.successful_parses += 1;
+                    if should_track_stats {
+                        stats.record(parse_result);
+                    }
+                    if let Some(stat_csv) = stat_csv.as_mut().filter(|_| time) {
+                        let mut file_stats = parse::Stats::default();
+                        file_stats.record(parse_result);
+                        file_stats.write_csv_row(stat_csv, &path.to_string_lossy())?;
                     }
-                    if let Some(duration) = parse_result.duration {
-                        stats.total_bytes += parse_result.bytes;
-                        stats.total_duration += duration;
+                    if let Some(duration) = parse_result.duration.filter(|_| time_json) {
+                        util::print_time_json(path, "parse", duration);
                     }
+
+                    has_error |= !parse_result.successful;
+                    has_error |= parse_result.exceeded_nesting_limit;
+                    total_error_cost += parse_result.error_cost;
                 }
+            }
+            progress.finish();
 
-                has_error |= !parse_result.successful;
+            if let Some(parsed_count) = parsed_count_at_deadline {
+                eprintln!("Deadline exceeded after parsing {parsed_count} of {path_count} files");
+                has_error = true;
             }
 
-            if should_track_stats {
+            if let Some(output_file) = output_file {
+                output_file.into_inner().flush()?;
+            }
+            if let Some(saved_tree_file) = saved_tree_file {
+                saved_tree_file.into_inner().flush()?;
+            }
+
+            if let Some(schema) = schema {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&schema.into_inner().to_json())?
+                );
+            }
+
+            if matches.is_present("error-cost") {
+                println!("{total_error_cost}");
+            }
+
+            if matches.is_present("stat") {
                 println!("\n{stats}");
             }
+            if let Some(stat_csv) = stat_csv.as_mut() {
+                stats.write_csv_row(stat_csv, "")?;
+            }
 
             if has_error {
                 return Err(anyhow!(""));
@@ -594,18 +1619,119 @@ fn run() -> Result<()> {
         }
 
         ("query", Some(matches)) => {
+            if matches.is_present("validate") {
+                let query_path = Path::new(matches.value_of("query-path").unwrap());
+                let loader_config = config.get()?;
+                loader.find_all_languages(&loader_config)?;
+                // The query file's own name doesn't say which language it's written for,
+                // so the language has to come from `--scope` or the current grammar.
+                let language = if let Some(scope) = matches.value_of("scope") {
+                    loader
+                        .language_configuration_for_scope(scope)
+                        .with_context(|| format!("Failed to load language for scope '{scope}'"))?
+                        .ok_or_else(|| anyhow!("Unknown scope '{scope}'"))?
+                        .0
+                } else {
+                    loader
+                        .languages_at_path(&current_dir)
+                        .with_context(|| "Failed to load language in current directory")?
+                        .into_iter()
+                        .next()
+                        .ok_or_else(|| {
+                            anyhow!(
+                                "No language to validate the query against. Pass --scope, or run this command in a grammar's directory"
+                            )
+                        })?
+                };
+                return query::validate_query_at_path(
+                    &language,
+                    query_path,
+                    matches.is_present("quiet"),
+                );
+            }
+
             let ordered_captures = matches.values_of("captures").is_some();
             let quiet = matches.values_of("quiet").is_some();
             let time = matches.values_of("time").is_some();
-            let paths = collect_paths(matches.value_of("paths-file"), matches.values_of("paths"))?;
+            let include = matches
+                .value_of("include")
+                .map(|s| Regex::new(s).with_context(|| format!("Invalid --include regex {s:?}")))
+                .transpose()?;
+            let exclude = matches
+                .value_of("exclude")
+                .map(|s| Regex::new(s).with_context(|| format!("Invalid --exclude regex {s:?}")))
+                .transpose()?;
+            let query_name = matches.value_of("query-name");
+            let mut positional_paths = matches.values_of("paths").map(Iterator::collect::<Vec<_>>);
+            // With `--query-name`, there is no query path, so the first positional
+            // argument is the first of the source paths.
+            if query_name.is_some() {
+                if let Some(path) = matches.value_of("query-path") {
+                    positional_paths
+                        .get_or_insert_with(Vec::new)
+                        .insert(0, path);
+                }
+            }
+            let paths = filter_modified_paths(
+                collect_paths(
+                    matches.value_of("paths-file"),
+                    positional_paths.map(Vec::into_iter),
+                    matches.is_present("respect-gitignore"),
+                    matches.is_present("recursive"),
+                )?,
+                matches,
+            )?;
+            // With `--newer-than` or `--modified-since`, there may be no files left
+            // when nothing has changed, and then there is nothing to query.
+            if paths.is_empty() {
+                return Ok(());
+            }
+            let mut paths = relative_paths(paths, matches, &current_dir);
+            if let Some(include) = &include {
+                paths.retain(|path| include.is_match(path));
+                if paths.is_empty() {
+                    return Err(anyhow!("No files match --include {:?}", include.as_str()));
+                }
+            }
+            if let Some(exclude) = &exclude {
+                paths.retain(|path| !exclude.is_match(path));
+                if paths.is_empty() {
+                    return Err(match &include {
+                        Some(include) => anyhow!(
+                            "All of the files that match --include {:?} also match --exclude {:?}",
+                            include.as_str(),
+                            exclude.as_str()
+                        ),
+                        None => anyhow!("All of the files match --exclude {:?}", exclude.as_str()),
+                    });
+                }
+            }
             let loader_config = config.get()?;
             loader.find_all_languages(&loader_config)?;
-            let language = loader.select_language(
-                Path::new(&paths[0]),
-                &current_dir,
-                matches.value_of("scope"),
-            )?;
-            let query_path = Path::new(matches.value_of("query-path").unwrap());
+            let (language, query_paths) = if let Some(name) = query_name {
+                let path = Path::new(&paths[0]);
+                let (language, language_config) = loader.select_language_configuration(
+                    path,
+                    &current_dir,
+                    matches.value_of("scope"),
+                )?;
+                let query_paths = language_config.query_paths(name);
+                if query_paths.is_empty() {
+                    return Err(anyhow!(
+                        "No {name} queries found for language {}",
+                        language_config.language_name
+                    ));
+                }
+                (language, query_paths)
+            } else {
+                let language = loader.select_language(
+                    Path::new(&paths[0]),
+                    &current_dir,
+                    matches.value_of("scope"),
+                )?;
+                let query_path = PathBuf::from(matches.value_of("query-path").unwrap());
+                (language, vec![query_path])
+            };
             let byte_range = matches.value_of("byte-range").and_then(|arg| {
                 let mut parts = arg.split(':');
                 let start = parts.next()?.parse().ok()?;
@@ -619,48 +1745,174 @@ fn run() -> Result<()> {
                 Some(Point::new(start, 0)..Point::new(end, 0))
             });
             let should_test = matches.is_present("test");
-            query::query_files_at_paths(
-                &language,
-                paths,
-                query_path,
+            let dot = matches.is_present("output-dot");
+            let max_start_depth = matches
+                .value_of("max-start-depth")
+                .map(str::parse::<u32>)
+                .transpose()
+                .with_context(|| "Invalid max start depth")?;
+            let context = matches
+                .value_of("context")
+                .map_or(Ok(0), str::parse::<usize>)
+                .with_context(|| "Invalid number of context lines")?;
+            let limit = matches
+                .value_of("limit")
+                .map(str::parse::<usize>)
+                .transpose()
+                .with_context(|| "Invalid match limit")?;
+            let limit_total = matches
+                .value_of("limit-total")
+                .map(str::parse::<usize>)
+                .transpose()
+                .with_context(|| "Invalid total match limit")?;
+            let opts = QueryFileOptions {
                 ordered_captures,
                 byte_range,
                 point_range,
                 should_test,
                 quiet,
-                time,
-            )?;
+                print_time: time,
+                time_json: matches.is_present("time-json"),
+                dot,
+                max_start_depth,
+                context,
+                capture_text: matches.value_of("capture-text").map(str::to_string),
+                limit,
+                limit_total,
+                any: matches.is_present("any"),
+                named_only: matches.is_present("named-only"),
+                group_by: matches.value_of("group-by").map(str::to_string),
+                xml: matches.is_present("xml"),
+                properties: matches.is_present("properties"),
+                report_unmatched: matches.is_present("report-unmatched"),
+                sort: matches.is_present("sort"),
+                position_encoding: matches
+                    .value_of("position-encoding")
+                    .map_or(Ok(util::PositionEncoding::default()), str::parse)?,
+            };
+            query::query_files_at_paths(&language, paths, &query_paths, &opts)?;
         }
 
         ("tags", Some(matches)) => {
             let loader_config = config.get()?;
             loader.find_all_languages(&loader_config)?;
-            let paths = collect_paths(matches.value_of("paths-file"), matches.values_of("paths"))?;
-            tags::generate_tags(
-                &loader,
-                matches.value_of("scope"),
-                &paths,
-                matches.is_present("quiet"),
-                matches.is_present("time"),
+            let paths = filter_modified_paths(
+                collect_paths(
+                    matches.value_of("paths-file"),
+                    matches.values_of("paths"),
+                    matches.is_present("respect-gitignore"),
+                    matches.is_present("recursive"),
+                )?,
+                matches,
+            )?;
+            let paths = relative_paths(paths, matches, &current_dir);
+            // The query paths are made absolute, because the loader reads relative
+            // query paths from the grammar's directory.
+            let query_paths = matches.values_of("tags-query").map(|paths| {
+                paths
+                    .map(|path| current_dir.join(path).to_string_lossy().to_string())
+                    .collect::<Vec<_>>()
+            });
+            let opts = tags::TagsOptions {
+                scope: matches.value_of("scope"),
+                quiet: matches.is_present("quiet"),
+                time: matches.is_present("time"),
+                time_json: matches.is_present("time-json"),
+                sort: matches.value_of("sort").unwrap().parse()?,
+                query_paths: query_paths.as_deref(),
+                exclude_extras: matches.is_present("exclude-extras"),
+            };
+            tags::generate_tags(&loader, &paths, &opts)?;
+        }
+
+        ("bench", Some(matches)) => {
+            let iterations = matches
+                .value_of("iterations")
+                .unwrap()
+                .parse::<usize>()
+                .with_context(|| "Invalid number of iterations")?;
+            let warmup = matches
+                .value_of("warmup")
+                .unwrap()
+                .parse::<usize>()
+                .with_context(|| "Invalid number of warmup iterations")?;
+            let paths = filter_modified_paths(
+                collect_paths(
+                    matches.value_of("paths-file"),
+                    matches.values_of("paths"),
+                    matches.is_present("respect-gitignore"),
+                    matches.is_present("recursive"),
+                )?,
+                matches,
             )?;
+            let paths = relative_paths(paths, matches, &current_dir);
+            loader.use_debug_build(matches.is_present("debug-build"));
+            let languages = loader.languages_at_path(&current_dir)?;
+            let language = languages
+                .first()
+                .ok_or_else(|| anyhow!("No language found"))?;
+            let opts = BenchOptions {
+                iterations,
+                warmup,
+                baseline: matches.value_of("baseline").map(Path::new),
+            };
+            bench::bench_files_at_paths(language, &paths, &opts)?;
         }
 
         ("highlight", Some(matches)) => {
-            let theme_config: tree_sitter_cli::highlight::ThemeConfig = config.get()?;
+            let mut theme_config: tree_sitter_cli::highlight::ThemeConfig = config.get()?;
+            if let Some(theme) = matches.value_of("theme") {
+                theme_config.theme = highlight::Theme::from_path_or_json(theme)?;
+            }
             loader.configure_highlights(&theme_config.theme.highlight_names);
             let loader_config = config.get()?;
             loader.find_all_languages(&loader_config)?;
+            if matches.is_present("force-ansi-color") {
+                util::init_color("always");
+            }
 
             let time = matches.is_present("time");
+            let time_json = matches.is_present("time-json");
             let quiet = matches.is_present("quiet");
             let html_mode = quiet || matches.is_present("html");
-            let should_check = matches.is_present("check");
-            let paths = collect_paths(matches.value_of("paths-file"), matches.values_of("paths"))?;
+            let should_check = matches.is_present("check") || matches.is_present("check-strict");
+            let mut check_failed = false;
+            // A path of `-` means that the source is read from stdin, in the language
+            // given by `--scope`, since there's no file name to choose it from.
+            let read_stdin = !matches.is_present("paths-file")
+                && matches
+                    .values_of("paths")
+                    .is_some_and(|mut paths| paths.any(|path| path == "-"));
+            let paths = if read_stdin {
+                if matches.values_of("paths").map_or(0, Iterator::count) > 1 {
+                    return Err(anyhow!(
+                        "Can't highlight stdin (-) together with other paths"
+                    ));
+                }
+                if !matches.is_present("scope") {
+                    return Err(anyhow!("Highlighting stdin (-) requires --scope"));
+                }
+                if matches.is_present("watch") {
+                    return Err(anyhow!("Can't watch stdin (-) for changes"));
+                }
+                vec!["-".to_string()]
+            } else {
+                let paths = filter_modified_paths(
+                    collect_paths(
+                        matches.value_of("paths-file"),
+                        matches.values_of("paths"),
+                        matches.is_present("respect-gitignore"),
+                        matches.is_present("recursive"),
+                    )?,
+                    matches,
+                )?;
+                relative_paths(paths, matches, &current_dir)
+            };
             let apply_all_captures = matches.is_present("apply-all-captures");
-
-            if html_mode && !quiet {
-                println!("{}", highlight::HTML_HEADER);
-            }
+            let position_encoding = matches
+                .value_of("position-encoding")
+                .map(str::parse)
+                .transpose()?;
 
             let cancellation_flag = util::cancel_on_signal();
 
@@ -679,8 +1931,63 @@ fn run() -> Result<()> {
                     .collect::<Vec<_>>()
             });
 
+            // With `--watch`, this process only watches the files, and the rendering is
+            // done by running the same command again without `--watch` after each change.
+            if matches.is_present("watch") {
+                let mut watched_paths = paths.iter().map(PathBuf::from).collect::<Vec<_>>();
+                if let Some(query_paths) = &query_paths {
+                    watched_paths.extend(query_paths.iter().map(PathBuf::from));
+                } else {
+                    for path in &paths {
+                        let language_config = match &language {
+                            Some((_, language_config)) => Some(*language_config),
+                            None => loader
+                                .language_configuration_for_file_name(Path::new(path))?
+                                .map(|(_, language_config)| language_config),
+                        };
+                        if let Some(language_config) = language_config {
+                            for name in ["highlights", "injections", "locals"] {
+                                watched_paths.extend(language_config.query_paths(name));
+                            }
+                        }
+                    }
+                }
+                match matches.value_of("theme") {
+                    Some(theme) if !theme.trim_start().starts_with(['{', '[']) => {
+                        watched_paths.push(PathBuf::from(theme));
+                    }
+                    Some(_) => {}
+                    None => watched_paths.push(config.location.clone()),
+                }
+                watched_paths.sort();
+                watched_paths.dedup();
+                return util::watch_paths(
+                    &watched_paths,
+                    !html_mode,
+                    Some(&cancellation_flag),
+                    || Ok(()),
+                );
+            }
+
+            if html_mode && !quiet {
+                if let Some(font_family) = matches.value_of("font") {
+                    println!("{}", highlight::html_header_with_font(font_family)?);
+                } else {
+                    println!("{}", highlight::HTML_HEADER);
+                }
+            }
+
+            let max_bytes = parse_max_bytes(matches)?;
+            let fail_on_skip = matches.is_present("fail-on-skip");
+            let mut progress = util::Progress::new(paths.len(), matches.is_present("progress"));
             for path in paths {
-                let path = Path::new(&path);
+                progress.tick();
+                let is_stdin = read_stdin && path == "-";
+                let path = Path::new(if is_stdin { "<stdin>" } else { &path });
+                if !is_stdin && skip_large_file(path, max_bytes)? {
+                    check_failed |= fail_on_skip;
+                    continue;
+                }
                 let (language, language_config) = match language.clone() {
                     Some(v) => v,
                     None => {
@@ -692,6 +1999,7 @@ fn run() -> Result<()> {
                         }
                     }
                 };
+                loader.log_language_selection(path, language_config);
 
                 if let Some(highlight_config) = language_config.highlight_config(
                     language,
@@ -729,18 +2037,63 @@ fn run() -> Result<()> {
                             for name in names {
                                 eprintln!("* {name}");
                             }
+                            check_failed = true;
+                        }
+                        if matches.is_present("check-strict") {
+                            let names = highlight_config.unrecognized_capture_names();
+                            if !names.is_empty() {
+                                eprintln!("Highlight captures not recognized by the theme:");
+                                for name in names {
+                                    eprintln!("* {name}");
+                                }
+                                check_failed = true;
+                            }
                         }
                     }
 
-                    let source = fs::read(path)?;
-                    if html_mode {
+                    let source = if is_stdin {
+                        let mut source = Vec::new();
+                        io::stdin()
+                            .read_to_end(&mut source)
+                            .context("Error reading source from stdin")?;
+                        source
+                    } else {
+                        fs::read(path)?
+                    };
+                    let start = Instant::now();
+                    let html_opts = highlight::HtmlOptions {
+                        quiet,
+                        print_time: time,
+                    };
+                    if matches.is_present("json") {
+                        highlight::json(
+                            &loader,
+                            path,
+                            &source,
+                            highlight_config,
+                            time,
+                            position_encoding,
+                            Some(&cancellation_flag),
+                        )?;
+                    } else if let Some(old_path) = matches.value_of("against") {
+                        let old_source = fs::read(old_path)
+                            .with_context(|| format!("Error reading source file {old_path:?}"))?;
+                        highlight::html_diff(
+                            &loader,
+                            &theme_config.theme,
+                            &old_source,
+                            &source,
+                            highlight_config,
+                            &html_opts,
+                            Some(&cancellation_flag),
+                        )?;
+                    } else if html_mode {
                         highlight::html(
                             &loader,
                             &theme_config.theme,
                             &source,
                             highlight_config,
-                            quiet,
-                            time,
+                            &html_opts,
                             Some(&cancellation_flag),
                         )?;
                     } else {
@@ -753,14 +2106,38 @@ fn run() -> Result<()> {
                             Some(&cancellation_flag),
                         )?;
                     }
+                    if time_json {
+                        util::print_time_json(path, "highlight", start.elapsed());
+                    }
                 } else {
                     eprintln!("No syntax highlighting config found for path {path:?}");
                 }
             }
+            progress.finish();
 
             if html_mode && !quiet {
                 println!("{}", highlight::HTML_FOOTER);
             }
+            if check_failed {
+                return Err(anyhow!(""));
+            }
+        }
+
+        ("build", Some(matches)) => {
+            let grammar_path = current_dir.join(matches.value_of("path").unwrap_or(""));
+            if let Some(path) = matches.value_of("libdir") {
+                loader = loader::Loader::with_parser_lib_path(PathBuf::from(path));
+                configure_loader(&mut loader, matches)?;
+            }
+            loader.use_debug_build(matches.is_present("debug-build"));
+            loader.use_static_build(matches.is_present("static"));
+            let library_paths = loader.compile_languages_at_path(&grammar_path)?;
+            if library_paths.is_empty() {
+                return Err(anyhow!("No language found in {}", grammar_path.display()));
+            }
+            for library_path in library_paths {
+                println!("{}", library_path.display());
+            }
         }
 
         ("build-wasm", Some(matches)) => {
@@ -770,6 +2147,7 @@ fn run() -> Result<()> {
                 &grammar_path,
                 &current_dir,
                 matches.is_present("docker"),
+                matches.is_present("dry-run"),
             )?;
         }
 
@@ -778,11 +2156,49 @@ fn run() -> Result<()> {
             playground::serve(&current_dir, open_in_browser)?;
         }
 
-        ("dump-languages", Some(_)) => {
+        ("inspect-language", Some(matches)) => {
+            let loader_config = config.get()?;
+            loader.find_all_languages(&loader_config)?;
+            // Without a scope, inspect the language in the current directory.
+            let scope = match matches.value_of("scope") {
+                Some(scope) => scope.to_string(),
+                None => loader
+                    .find_language_configurations_at_path(&current_dir, true)?
+                    .first()
+                    .and_then(|config| config.scope.clone())
+                    .ok_or_else(|| {
+                        anyhow!("No language with a scope found in the current directory")
+                    })?,
+            };
+            let (language, language_config) = loader
+                .language_configuration_for_scope(&scope)?
+                .ok_or_else(|| anyhow!("Unknown scope '{scope}'"))?;
+            inspect_language(&language, language_config)?;
+        }
+
+        ("dump-languages", Some(matches)) => {
             let loader_config = config.get()?;
             loader.find_all_languages(&loader_config)?;
+            if matches.is_present("check-conflicts") {
+                return check_language_conflicts(&loader);
+            }
+            let abi_only = matches.is_present("abi-only");
+            let show_abi = abi_only || matches.is_present("abi");
+            let supported_abi_versions =
+                tree_sitter::MIN_COMPATIBLE_LANGUAGE_VERSION..=tree_sitter::LANGUAGE_VERSION;
             for (configuration, language_path) in loader.get_all_language_configurations() {
-                println!(
+                let abi_version = show_abi.then(|| {
+                    loader
+                        .language_for_configuration(configuration)
+                        .map(|language| language.version())
+                });
+                if abi_only
+                    && matches!(abi_version, Some(Ok(version)) if supported_abi_versions.contains(&version))
+                {
+                    continue;
+                }
+
+                print!(
                     concat!(
                         "scope: {}\n",
                         "parser: {:?}\n",
@@ -798,6 +2214,19 @@ fn run() -> Result<()> {
                     configuration.content_regex,
                     configuration.injection_regex,
                 );
+                match abi_version {
+                    Some(Ok(version)) if supported_abi_versions.contains(&version) => {
+                        println!("abi_version: {version}");
+                    }
+                    Some(Ok(version)) => println!(
+                        "abi_version: {version} (unsupported, expected {} to {})",
+                        supported_abi_versions.start(),
+                        supported_abi_versions.end()
+                    ),
+                    Some(Err(error)) => println!("abi_version: failed to load the parser: {error}"),
+                    None => {}
+                }
+                println!();
             }
         }
 
@@ -807,57 +2236,644 @@ fn run() -> Result<()> {
     Ok(())
 }
 
+/// Print the named node types and the field names of a language, and the capture
+/// names used by its highlights and tags queries.
+fn inspect_language(language: &Language, config: &loader::LanguageConfiguration) -> Result<()> {
+    let mut node_types = (0..language.node_kind_count() as u16)
+        .filter(|id| language.node_kind_is_named(*id) && language.node_kind_is_visible(*id))
+        .filter_map(|id| language.node_kind_for_id(id))
+        .collect::<Vec<_>>();
+    node_types.sort_unstable();
+    node_types.dedup();
+    println!("node types:");
+    for node_type in node_types {
+        println!("  {node_type}");
+    }
+
+    let mut field_names = (1..=language.field_count() as u16)
+        .filter_map(|id| language.field_name_for_id(id))
+        .collect::<Vec<_>>();
+    field_names.sort_unstable();
+    println!("fields:");
+    for field_name in field_names {
+        println!("  {field_name}");
+    }
+
+    for query_name in ["highlights", "tags"] {
+        let query_paths = config.query_paths(query_name);
+        if query_paths.is_empty() {
+            println!("{query_name} captures: no {query_name} queries found");
+            continue;
+        }
+        let mut query_source = String::new();
+        for query_path in &query_paths {
+            query_source += &fs::read_to_string(query_path)
+                .with_context(|| format!("Error reading query file {query_path:?}"))?;
+        }
+        let query = tree_sitter::Query::new(language, &query_source)
+            .with_context(|| format!("Failed to compile the {query_name} queries"))?;
+        let mut capture_names = query.capture_names().to_vec();
+        capture_names.sort_unstable();
+        println!("{query_name} captures:");
+        for capture_name in capture_names {
+            println!("  @{capture_name}");
+        }
+    }
+    Ok(())
+}
+
+/// Write the default config file, or the file of the given profile.
+fn init_config(matches: &ArgMatches, profile: Option<&str>) -> Result<()> {
+    let mut config = match profile {
+        Some(name) => Config::initial_profile(name)?,
+        None => Config::initial()?,
+    };
+    config.add(tree_sitter_loader::Config::initial())?;
+    config.add(tree_sitter_cli::highlight::ThemeConfig::default())?;
+    if matches.is_present("print") {
+        println!("{}", serde_json::to_string_pretty(&config.config)?);
+        return Ok(());
+    }
+
+    let existing_path = match profile {
+        Some(name) => Config::find_profile_file(name),
+        None => Config::find_config_file(),
+    };
+    if let Ok(Some(config_path)) = existing_path {
+        if !matches.is_present("force") {
+            return Err(anyhow!(
+                "Remove your existing config file first, or pass --force to overwrite it: {}",
+                config_path.to_string_lossy()
+            ));
+        }
+        let backup_path = config_path.with_extension("json.bak");
+        fs::copy(&config_path, &backup_path)
+            .with_context(|| format!("Failed to back up {}", config_path.to_string_lossy()))?;
+        println!(
+            "Backed up the existing configuration to {}",
+            backup_path.display()
+        );
+        config.location = config_path;
+    }
+    config.save()?;
+    println!(
+        "Saved initial configuration to {}",
+        config.location.display()
+    );
+    Ok(())
+}
+
+/// Apply the global options that control how the loader builds parsers.
+fn configure_loader(loader: &mut loader::Loader, matches: &ArgMatches) -> Result<()> {
+    loader.set_verbose(matches.is_present("verbose"));
+    loader.set_c_compiler(
+        matches
+            .value_of_os("cc")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("CC").map(PathBuf::from)),
+    );
+    if let Some(scanner_path) = matches.value_of_os("scanner").map(PathBuf::from) {
+        if !scanner_path.is_file() {
+            return Err(anyhow!(
+                "The scanner {} doesn't exist",
+                scanner_path.display()
+            ));
+        }
+        // The scanner only replaces the one of the grammar that the command works on:
+        // the language given with `--scope`, or else the grammar in the current
+        // directory, or the one given by the command's grammar directory argument.
+        let args = matches.subcommand().1.unwrap_or(matches);
+        let target = if let Some(scope) = args.value_of("scope") {
+            loader::ScannerTarget::Scope(scope.to_string())
+        } else {
+            let grammar_dir = args
+                .value_of("grammar-dir")
+                .or_else(|| args.value_of("path"))
+                .unwrap_or("");
+            loader::ScannerTarget::Directory(env::current_dir()?.join(grammar_dir))
+        };
+        loader.set_scanner_path(scanner_path, target);
+    }
+    loader.set_c_flags(
+        matches
+            .value_of("cflags")
+            .map(str::to_string)
+            .or_else(|| env::var("CFLAGS").ok())
+            .map(|flags| flags.split_whitespace().map(str::to_string).collect())
+            .unwrap_or_default(),
+    );
+    if let Some(build_jobs) = matches.value_of("build-jobs") {
+        let build_jobs = build_jobs
+            .parse::<usize>()
+            .ok()
+            .filter(|jobs| *jobs > 0)
+            .ok_or_else(|| anyhow!("Invalid number of build jobs: {build_jobs}"))?;
+        loader.set_build_jobs(build_jobs);
+    }
+    Ok(())
+}
+
+/// Give the parser a wasm store, and have the loader compile languages to wasm. With
+/// `--wasm-fuel`, each parse is halted once it has consumed that much fuel.
+#[cfg(feature = "wasm")]
+fn configure_wasm(
+    parser: &mut Parser,
+    loader: &mut loader::Loader,
+    matches: &ArgMatches,
+) -> Result<()> {
+    let wasm_fuel = matches
+        .value_of("wasm-fuel")
+        .map(|fuel| {
+            fuel.parse::<u64>()
+                .with_context(|| format!("Invalid wasm fuel: {fuel}"))
+        })
+        .transpose()?;
+    let engine = if wasm_fuel.is_some() {
+        tree_sitter::wasmtime::Engine::new(tree_sitter::wasmtime::Config::new().consume_fuel(true))?
+    } else {
+        tree_sitter::wasmtime::Engine::default()
+    };
+    let mut store = tree_sitter::WasmStore::new(engine.clone())?;
+    if let Some(fuel) = wasm_fuel {
+        store.set_fuel(fuel);
+    }
+    parser.set_wasm_store(store)?;
+    loader.use_wasm(engine);
+    Ok(())
+}
+
+// Report the file types that are claimed by more than one language, and the content
+// regexes that are shared by more than one language, since the loader can't reliably
+// choose between them. Content regexes are compared as strings, so regexes that are
+// written differently but match the same text aren't reported.
+fn check_language_conflicts(loader: &loader::Loader) -> Result<()> {
+    let configurations = loader.get_all_language_configurations();
+    let scope = |configuration: &loader::LanguageConfiguration| {
+        configuration
+            .scope
+            .clone()
+            .unwrap_or_else(|| configuration.root_path.display().to_string())
+    };
+
+    let mut scopes_by_file_type = BTreeMap::<&str, Vec<_>>::new();
+    let mut scopes_by_content_regex = BTreeMap::<&str, Vec<_>>::new();
+    for (configuration, _) in &configurations {
+        for file_type in &configuration.file_types {
+            scopes_by_file_type
+                .entry(file_type)
+                .or_default()
+                .push(configuration);
+        }
+        if let Some(regex) = &configuration.content_regex {
+            scopes_by_content_regex
+                .entry(regex.as_str())
+                .or_default()
+                .push(configuration);
+        }
+    }
+
+    let mut conflict_count = 0;
+    for (file_type, configurations) in &scopes_by_file_type {
+        if configurations.len() > 1 {
+            conflict_count += 1;
+            println!("File type {file_type:?} is claimed by:");
+            for configuration in configurations {
+                match &configuration.content_regex {
+                    Some(regex) => println!(
+                        "  {} (content_regex: {:?})",
+                        scope(configuration),
+                        regex.as_str()
+                    ),
+                    None => println!("  {}", scope(configuration)),
+                }
+            }
+        }
+    }
+    for (regex, configurations) in &scopes_by_content_regex {
+        if configurations.len() > 1 {
+            conflict_count += 1;
+            println!("Content regex {regex:?} is shared by:");
+            for configuration in configurations {
+                println!("  {}", scope(configuration));
+            }
+        }
+    }
+
+    if conflict_count == 0 {
+        println!("No conflicts found");
+        Ok(())
+    } else {
+        Err(anyhow!(""))
+    }
+}
+
+fn parse_max_bytes(matches: &ArgMatches) -> Result<Option<u64>> {
+    matches
+        .value_of("max-bytes")
+        .map(str::parse::<u64>)
+        .transpose()
+        .context("Invalid maximum file size")
+}
+
+/// With `--max-bytes`, check the size of a file before reading it, and warn that it
+/// is being skipped if it is too large.
+fn skip_large_file(path: &Path, max_bytes: Option<u64>) -> Result<bool> {
+    let Some(max_bytes) = max_bytes else {
+        return Ok(false);
+    };
+    let size = fs::metadata(path)
+        .with_context(|| format!("Error reading source file {path:?}"))?
+        .len();
+    if size > max_bytes {
+        eprintln!(
+            "Warning: skipping {}: {size} bytes is more than the maximum of {max_bytes}",
+            path.display()
+        );
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+/// Select the languages to parse a file with. When several scopes are given, the file
+/// is parsed with each of their languages and the number of errors is reported on
+/// stderr, and only the language with the fewest errors is selected, unless
+/// `all_scopes` is set.
+fn select_parse_languages(
+    loader: &mut loader::Loader,
+    parser: &mut Parser,
+    path: &Path,
+    current_dir: &Path,
+    scopes: &[&str],
+    all_scopes: bool,
+) -> Result<Vec<Language>> {
+    if scopes.len() <= 1 {
+        let language = loader.select_language(path, current_dir, scopes.first().copied())?;
+        parser
+            .set_language(&language)
+            .context("incompatible language")?;
+        return Ok(vec![language]);
+    }
+
+    let source_code =
+        fs::read(path).with_context(|| format!("Error reading source file {path:?}"))?;
+    let mut candidates = Vec::new();
+    for scope in scopes {
+        let language = loader.select_language(path, current_dir, Some(scope))?;
+        parser
+            .set_language(&language)
+            .context("incompatible language")?;
+        let tree = parser
+            .parse(&source_code, None)
+            .ok_or_else(|| anyhow!("Parsing of {path:?} was cancelled"))?;
+        let error_count = parse::count_errors(&tree);
+        eprintln!("{}\t{scope}\t{error_count} errors", path.display());
+        candidates.push((language, error_count));
+    }
+
+    if all_scopes {
+        Ok(candidates
+            .into_iter()
+            .map(|(language, _)| language)
+            .collect())
+    } else {
+        let (language, _) = candidates
+            .into_iter()
+            .min_by_key(|(_, error_count)| *error_count)
+            .unwrap();
+        Ok(vec![language])
+    }
+}
+
 fn collect_paths<'a>(
     paths_file: Option<&str>,
     paths: Option<impl Iterator<Item = &'a str>>,
+    respect_gitignore: bool,
+    recursive: bool,
 ) -> Result<Vec<String>> {
+    // The patterns in a `.tsignore` file are excluded as though they had been
+    // passed as negated paths after all of the other paths.
+    let ignored_paths = match fs::read_to_string(".tsignore") {
+        Ok(contents) => non_comment_lines(&contents)
+            .map(|line| format!("!{}", line.trim_start_matches('!')))
+            .collect::<Vec<_>>(),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(error) => return Err(Error::from(error).context("Failed to read .tsignore")),
+    };
+    let ignored_paths = ignored_paths.iter().map(String::as_str);
+
     if let Some(paths_file) = paths_file {
-        return Ok(fs::read_to_string(paths_file)
-            .with_context(|| format!("Failed to read paths file {paths_file}"))?
-            .trim()
-            .lines()
-            .map(String::from)
-            .collect::<Vec<_>>());
+        let contents = fs::read_to_string(paths_file)
+            .with_context(|| format!("Failed to read paths file {paths_file}"))?;
+        return expand_paths(
+            non_comment_lines(&contents).chain(ignored_paths),
+            respect_gitignore,
+            recursive,
+        );
     }
 
     if let Some(paths) = paths {
-        let mut result = Vec::new();
+        let paths = paths.collect::<Vec<_>>();
+        return expand_paths(
+            paths.into_iter().chain(ignored_paths),
+            respect_gitignore,
+            recursive,
+        );
+    }
+
+    Err(anyhow!("Must provide one or more paths"))
+}
 
-        let mut incorporate_path = |path: &str, positive| {
-            if positive {
-                result.push(path.to_string());
-            } else if let Some(index) = result.iter().position(|p| p == path) {
-                result.remove(index);
+/// With `--newer-than` or `--modified-since`, keep only the files that were modified
+/// after the given time. Files whose modification time can't be read are kept.
+fn filter_modified_paths(paths: Vec<String>, matches: &ArgMatches) -> Result<Vec<String>> {
+    // A file's modification time must be after that of the `--newer-than` file, but
+    // it may be equal to the `--modified-since` timestamp.
+    let (reference_time, inclusive) = if let Some(path) = matches.value_of("newer-than") {
+        let time = fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .with_context(|| format!("Failed to read the modification time of {path}"))?;
+        (time, false)
+    } else if let Some(timestamp) = matches.value_of("modified-since") {
+        let seconds = timestamp
+            .parse::<u64>()
+            .context("Invalid timestamp, expected a number of seconds since the Unix epoch")?;
+        (SystemTime::UNIX_EPOCH + Duration::from_secs(seconds), true)
+    } else {
+        return Ok(paths);
+    };
+
+    let mut result = Vec::new();
+    for path in paths {
+        match fs::metadata(&path).and_then(|metadata| metadata.modified()) {
+            Ok(time) if time < reference_time || (time == reference_time && !inclusive) => {}
+            Ok(_) => result.push(path),
+            Err(error) => {
+                eprintln!(
+                    "Warning: including {path}, whose modification time can't be read: {error}"
+                );
+                result.push(path);
             }
-        };
+        }
+    }
+    Ok(result)
+}
+
+// With `--relative-paths`, rewrite the input paths relative to the current directory,
+// so that the output doesn't depend on where the files were found from.
+fn relative_paths(paths: Vec<String>, matches: &ArgMatches, current_dir: &Path) -> Vec<String> {
+    if !matches.is_present("relative-paths") {
+        return paths;
+    }
+    paths
+        .into_iter()
+        .map(|path| relative_path(Path::new(&path), current_dir))
+        .collect()
+}
 
-        for mut path in paths {
-            let mut positive = true;
-            if path.starts_with('!') {
-                positive = false;
-                path = path.trim_start_matches('!');
+fn relative_path(path: &Path, current_dir: &Path) -> String {
+    let components = path
+        .components()
+        .filter(|component| *component != Component::CurDir)
+        .collect::<Vec<_>>();
+    let result = if path.is_absolute() {
+        let dir_components = current_dir.components().collect::<Vec<_>>();
+        let common_len = components
+            .iter()
+            .zip(&dir_components)
+            .take_while(|(a, b)| a == b)
+            .count();
+        // Paths on another drive can't be made relative.
+        if common_len == 0 {
+            return path.to_string_lossy().to_string();
+        }
+        std::iter::repeat(Component::ParentDir)
+            .take(dir_components.len() - common_len)
+            .chain(components[common_len..].iter().copied())
+            .collect::<PathBuf>()
+    } else {
+        components.into_iter().collect::<PathBuf>()
+    };
+    if result.as_os_str().is_empty() {
+        ".".to_string()
+    } else {
+        result.to_string_lossy().to_string()
+    }
+}
+
+fn non_comment_lines(contents: &str) -> impl Iterator<Item = &str> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+}
+
+// Expand the `{a,b}` alternations in a glob pattern, which the `glob` crate doesn't
+// support, into separate patterns, like a shell would. Braces without a comma
+// between them are left as they are.
+fn expand_braces(pattern: &str) -> Vec<String> {
+    let mut depth = 0;
+    let mut group_start = 0;
+    let mut alternative_starts = Vec::new();
+    for (i, c) in pattern.char_indices() {
+        match c {
+            '{' => {
+                if depth == 0 {
+                    group_start = i;
+                    alternative_starts.clear();
+                    alternative_starts.push(i + 1);
+                }
+                depth += 1;
             }
+            ',' if depth == 1 => alternative_starts.push(i + 1),
+            '}' if depth > 0 => {
+                depth -= 1;
+                if depth == 0 && alternative_starts.len() > 1 {
+                    let prefix = &pattern[..group_start];
+                    let suffix = &pattern[i + 1..];
+                    let alternative_ends = alternative_starts[1..]
+                        .iter()
+                        .map(|start| start - 1)
+                        .chain(Some(i));
+                    return alternative_starts
+                        .iter()
+                        .zip(alternative_ends)
+                        .flat_map(|(start, end)| {
+                            expand_braces(&format!("{prefix}{}{suffix}", &pattern[*start..end]))
+                        })
+                        .collect();
+                }
+            }
+            _ => {}
+        }
+    }
+    vec![pattern.to_string()]
+}
+
+fn expand_paths<'a>(
+    paths: impl Iterator<Item = &'a str>,
+    respect_gitignore: bool,
+    recursive: bool,
+) -> Result<Vec<String>> {
+    let mut result = Vec::new();
+
+    let mut incorporate_path = |path: &str, positive| {
+        if positive {
+            result.push(path.to_string());
+        } else if let Some(index) = result.iter().position(|p| p == path) {
+            result.remove(index);
+        }
+    };
+
+    for mut path in paths {
+        let mut positive = true;
+        if path.starts_with('!') {
+            positive = false;
+            path = path.trim_start_matches('!');
+        }
 
-            if Path::new(path).exists() {
+        if Path::new(path).is_dir() {
+            // A directory stands for all of the files inside of it, including the ones
+            // whose names start with a dot, which a `**/*` glob would miss.
+            if !recursive {
+                if positive {
+                    return Err(anyhow!(
+                        "{path} is a directory. Pass --recursive to include the files inside of it"
+                    ));
+                }
                 incorporate_path(path, positive);
-            } else {
-                let paths = glob(path).with_context(|| format!("Invalid glob pattern {path:?}"))?;
-                for path in paths {
+                continue;
+            }
+            let mut matched_paths = Vec::new();
+            for entry in WalkDir::new(path).sort_by_file_name() {
+                let entry = entry.with_context(|| format!("Failed to read directory {path}"))?;
+                if entry.file_type().is_file() {
+                    if let Some(path) = entry.path().to_str() {
+                        matched_paths.push(path.to_string());
+                    }
+                }
+            }
+            if respect_gitignore {
+                matched_paths = remove_git_ignored_paths(matched_paths)?;
+            }
+            for path in &matched_paths {
+                incorporate_path(path, positive);
+            }
+        } else if Path::new(path).exists() {
+            incorporate_path(path, positive);
+        } else {
+            let mut matched_paths = Vec::new();
+            for pattern in expand_braces(path) {
+                for path in
+                    glob(&pattern).with_context(|| format!("Invalid glob pattern {pattern:?}"))?
+                {
                     if let Some(path) = path?.to_str() {
-                        incorporate_path(path, positive);
+                        matched_paths.push(path.to_string());
                     }
                 }
             }
+            if respect_gitignore {
+                matched_paths = remove_git_ignored_paths(matched_paths)?;
+            }
+            for path in &matched_paths {
+                incorporate_path(path, positive);
+            }
         }
+    }
 
-        if result.is_empty() {
-            return Err(anyhow!(
-                "No files were found at or matched by the provided pathname/glob"
-            ));
-        }
+    if result.is_empty() {
+        return Err(anyhow!(
+            "No files were found at or matched by the provided pathname/glob"
+        ));
+    }
+
+    Ok(result)
+}
 
-        return Ok(result);
+// Filter out the paths that git considers ignored. If git is unavailable, or the
+// paths are not inside of a git repository, then the paths are returned unchanged.
+fn remove_git_ignored_paths(paths: Vec<String>) -> Result<Vec<String>> {
+    if paths.is_empty() {
+        return Ok(paths);
     }
 
-    Err(anyhow!("Must provide one or more paths"))
+    let Ok(mut git_process) = Command::new("git")
+        .args(["check-ignore", "--stdin", "-z"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+    else {
+        return Ok(paths);
+    };
+
+    let mut git_stdin = git_process
+        .stdin
+        .take()
+        .ok_or_else(|| anyhow!("Failed to open stdin for `git` process."))?;
+    let input = paths.iter().fold(String::new(), |mut input, path| {
+        input.push_str(path);
+        input.push('\0');
+        input
+    });
+    let writer = thread::spawn(move || git_stdin.write_all(input.as_bytes()));
+    let output = git_process
+        .wait_with_output()
+        .with_context(|| "Failed to read output from `git check-ignore`")?;
+    let write_result = writer
+        .join()
+        .map_err(|_| anyhow!("Failed to write paths to `git check-ignore`"))?;
+
+    // `git check-ignore` exits with 0 if some paths are ignored, 1 if none are,
+    // and 128 if it could not run (e.g. outside of a repository).
+    if output.status.code() != Some(0) {
+        return Ok(paths);
+    }
+    write_result?;
+    let ignored_paths = output
+        .stdout
+        .split(|b| *b == 0)
+        .filter_map(|path| std::str::from_utf8(path).ok())
+        .collect::<HashSet<_>>();
+    Ok(paths
+        .into_iter()
+        .filter(|path| !ignored_paths.contains(path.as_str()))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_braces() {
+        assert_eq!(expand_braces("src/*.{c,h}"), ["src/*.c", "src/*.h"]);
+        assert_eq!(expand_braces("{a,b}{c,d}"), ["ac", "ad", "bc", "bd"]);
+        assert_eq!(expand_braces("no-braces/*.rs"), ["no-braces/*.rs"]);
+    }
+
+    #[test]
+    fn test_expand_braces_nested() {
+        assert_eq!(
+            expand_braces("{a,b{c,d}}.txt"),
+            ["a.txt", "bc.txt", "bd.txt"]
+        );
+        assert_eq!(expand_braces("{x{1,2},y}"), ["x1", "x2", "y"]);
+    }
+
+    #[test]
+    fn test_expand_braces_with_empty_alternatives() {
+        assert_eq!(expand_braces("test{,-old}.js"), ["test.js", "test-old.js"]);
+        assert_eq!(expand_braces("{,}"), ["", ""]);
+    }
+
+    #[test]
+    fn test_expand_braces_without_alternatives() {
+        // Braces without a comma, or that are never closed, are left as they are.
+        assert_eq!(expand_braces("{a}"), ["{a}"]);
+        assert_eq!(expand_braces("{a}.{b,c}"), ["{a}.b", "{a}.c"]);
+        assert_eq!(expand_braces("{a,b"), ["{a,b"]);
+        assert_eq!(expand_braces("{{a,b}"), ["{{a,b}"]);
+        assert_eq!(expand_braces("a,b}"), ["a,b}"]);
+        assert_eq!(expand_braces("{a,b}}"), ["a}", "b}"]);
+    }
 }
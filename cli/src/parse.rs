@@ -1,11 +1,14 @@
 use super::util;
 use anyhow::{anyhow, Context, Result};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::io::{self, Write};
+use std::ops::Range;
 use std::path::Path;
 use std::sync::atomic::AtomicUsize;
 use std::time::{Duration, Instant};
 use std::{fmt, fs, usize};
-use tree_sitter::{ffi, InputEdit, Language, LogType, Parser, Point, Tree};
+use tree_sitter::{ffi, InputEdit, Language, LogType, Node, Parser, Point, Tree};
 
 #[derive(Debug)]
 pub struct Edit {
@@ -20,6 +23,7 @@ pub struct Stats {
     pub total_parses: usize,
     pub total_bytes: usize,
     pub total_duration: Duration,
+    pub node_counts: Option<NodeCounts>,
 }
 
 impl fmt::Display for Stats {
@@ -37,30 +41,508 @@ impl fmt::Display for Stats {
             } else {
                 0
             }
+        )?;
+        if let Some(counts) = self.node_counts {
+            writeln!(
+                f,
+                "Total nodes: {}; named nodes: {}; max depth: {}",
+                counts.total, counts.named, counts.max_depth
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Stats {
+    pub const CSV_HEADER: &'static str =
+        "path,total_parses,successful_parses,total_bytes,total_duration_ms,bytes_per_sec";
+
+    pub fn record(&mut self, result: ParseResult) {
+        self.total_parses += 1;
+        if result.successful {
+            self.successful_parses += 1;
+        }
+        if let Some(duration) = result.duration {
+            self.total_bytes += result.bytes;
+            self.total_duration += duration;
+        }
+        if let Some(counts) = result.node_counts {
+            let total = self.node_counts.get_or_insert_with(NodeCounts::default);
+            total.total += counts.total;
+            total.named += counts.named;
+            total.max_depth = total.max_depth.max(counts.max_depth);
+        }
+    }
+
+    /// Write these statistics as a row of CSV, labelled with the given path.
+    pub fn write_csv_row(&self, writer: &mut impl Write, path: &str) -> io::Result<()> {
+        let duration_secs = self.total_duration.as_secs_f64();
+        writeln!(
+            writer,
+            "{},{},{},{},{:.3},{:.0}",
+            escape_csv_field(path),
+            self.total_parses,
+            self.successful_parses,
+            self.total_bytes,
+            duration_secs * 1e3,
+            if duration_secs != 0.0 {
+                self.total_bytes as f64 / duration_secs
+            } else {
+                0.0
+            }
         )
     }
 }
 
+fn escape_csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
 #[derive(Copy, Clone)]
 pub enum ParseOutput {
     Normal,
     Quiet,
     Xml,
     Dot,
+    Json,
+    Cst,
+    Ast,
+}
+
+/// With `--output`, separate the trees of the files in the output file with a comment
+/// that names each file. The JSON formats have no comments, and are left as they are.
+pub fn write_output_file_header(
+    output: &mut impl Write,
+    format: ParseOutput,
+    path: &Path,
+) -> Result<()> {
+    match format {
+        ParseOutput::Normal | ParseOutput::Cst => writeln!(output, "; {}", path.display())?,
+        ParseOutput::Xml => writeln!(output, "<!-- {} -->", path.display())?,
+        ParseOutput::Quiet | ParseOutput::Dot | ParseOutput::Json | ParseOutput::Ast => {}
+    }
+    Ok(())
+}
+
+/// The start of a file written by `--save-tree`. The last byte is the version of the format.
+pub const SAVED_TREE_HEADER: &[u8] = b"tree-sitter-saved-tree\x00\x01";
+
+const SAVED_NODE_NAMED: u8 = 1;
+const SAVED_NODE_EXTRA: u8 = 2;
+const SAVED_NODE_ERROR: u8 = 4;
+const SAVED_NODE_MISSING: u8 = 8;
+
+/// With `--save-tree`, append the file's tree to the saved tree file. The library can't
+/// rebuild a `Tree` from its nodes, so this records what's needed to print the tree
+/// again without the grammar or the source: the path, the node type and field names,
+/// and each node's flags, ranges and number of children, in pre-order. All numbers are
+/// little-endian `u32`s, and strings are prefixed with their length.
+pub fn write_saved_tree(output: &mut impl Write, path: &Path, tree: &Tree) -> Result<()> {
+    let mut kinds = Vec::new();
+    let mut kind_indices = HashMap::new();
+    let mut fields = Vec::new();
+    let mut field_indices = HashMap::new();
+    let mut nodes = Vec::new();
+    let mut cursor = tree.walk();
+    let mut did_visit_children = false;
+    loop {
+        if did_visit_children {
+            if cursor.goto_next_sibling() {
+                did_visit_children = false;
+            } else if !cursor.goto_parent() {
+                break;
+            }
+            continue;
+        }
+        let node = cursor.node();
+        let kind_index = *kind_indices.entry(node.kind()).or_insert_with(|| {
+            kinds.push(node.kind());
+            kinds.len() - 1
+        });
+        // Field indices start at 1, so that 0 means that the node has no field.
+        let field_index = cursor.field_name().map_or(0, |field_name| {
+            *field_indices.entry(field_name).or_insert_with(|| {
+                fields.push(field_name);
+                fields.len()
+            })
+        });
+        let mut flags = 0;
+        for (flag, is_set) in [
+            (SAVED_NODE_NAMED, node.is_named()),
+            (SAVED_NODE_EXTRA, node.is_extra()),
+            (SAVED_NODE_ERROR, node.is_error()),
+            (SAVED_NODE_MISSING, node.is_missing()),
+        ] {
+            if is_set {
+                flags |= flag;
+            }
+        }
+        let start = node.start_position();
+        let end = node.end_position();
+        nodes.push((
+            flags,
+            [
+                kind_index,
+                field_index,
+                node.start_byte(),
+                node.end_byte(),
+                start.row,
+                start.column,
+                end.row,
+                end.column,
+                node.child_count(),
+            ],
+        ));
+        did_visit_children = !cursor.goto_first_child();
+    }
+
+    let write_u32 = |output: &mut dyn Write, n: usize| -> Result<()> {
+        let n = u32::try_from(n).context("The tree is too large to save")?;
+        output.write_all(&n.to_le_bytes())?;
+        Ok(())
+    };
+    let write_str = |output: &mut dyn Write, s: &str| -> Result<()> {
+        write_u32(output, s.len())?;
+        output.write_all(s.as_bytes())?;
+        Ok(())
+    };
+    write_str(output, &path.to_string_lossy())?;
+    write_u32(output, kinds.len())?;
+    for kind in kinds {
+        write_str(output, kind)?;
+    }
+    write_u32(output, fields.len())?;
+    for field in fields {
+        write_str(output, field)?;
+    }
+    write_u32(output, nodes.len())?;
+    for (flags, values) in nodes {
+        output.write_all(&[flags])?;
+        for value in values {
+            write_u32(output, value)?;
+        }
+    }
+    Ok(())
+}
+
+/// With `--load-tree`, print the trees in a file written by `--save-tree`, in the same
+/// form as the default output of `parse`.
+pub fn print_saved_trees(path: &Path, opts: &SexpOptions) -> Result<()> {
+    let contents =
+        fs::read(path).with_context(|| format!("Error reading saved tree file {path:?}"))?;
+    let contents = contents
+        .strip_prefix(SAVED_TREE_HEADER)
+        .ok_or_else(|| anyhow!("{path:?} is not a tree file written by --save-tree"))?;
+    let stdout = io::stdout();
+    write_saved_trees(&mut stdout.lock(), contents, opts)
+}
+
+/// Write the trees in the contents of a saved tree file, after its header, as
+/// S-expressions. When there's more than one tree, each one is preceded by a comment
+/// with its path.
+pub fn write_saved_trees(
+    output: &mut impl Write,
+    contents: &[u8],
+    opts: &SexpOptions,
+) -> Result<()> {
+    struct Reader<'a>(&'a [u8]);
+
+    impl<'a> Reader<'a> {
+        fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+            if self.0.len() < len {
+                return Err(anyhow!("The saved tree file is truncated"));
+            }
+            let (bytes, rest) = self.0.split_at(len);
+            self.0 = rest;
+            Ok(bytes)
+        }
+
+        fn u32(&mut self) -> Result<usize> {
+            let bytes = self.bytes(4)?;
+            Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+        }
+
+        fn string(&mut self) -> Result<&'a str> {
+            let len = self.u32()?;
+            std::str::from_utf8(self.bytes(len)?).context("The saved tree file is invalid")
+        }
+    }
+
+    // A node that is still open, because some of its descendants haven't been read yet.
+    struct OpenNode {
+        remaining_child_count: usize,
+        is_printed: bool,
+        is_dropped: bool,
+    }
+
+    let invalid = || anyhow!("The saved tree file is invalid");
+    let mut reader = Reader(contents);
+    let mut trees = Vec::new();
+    while !reader.0.is_empty() {
+        let mut tree_output = Vec::new();
+        let tree_path = reader.string()?;
+        let kinds = (0..reader.u32()?)
+            .map(|_| reader.string())
+            .collect::<Result<Vec<_>>>()?;
+        let fields = (0..reader.u32()?)
+            .map(|_| reader.string())
+            .collect::<Result<Vec<_>>>()?;
+        let node_count = reader.u32()?;
+
+        // The nodes are in pre-order, so the stack of open nodes is the path from the
+        // root to the next node.
+        let mut printer = SexpPrinter::new(opts);
+        let mut stack = Vec::<OpenNode>::new();
+        for i in 0..node_count {
+            let flags = reader.bytes(1)?[0];
+            let mut values = [0; 9];
+            for value in &mut values {
+                *value = reader.u32()?;
+            }
+            let [kind_index, field_index, _, _, start_row, start_column, end_row, end_column, child_count] =
+                values;
+
+            if let Some(parent) = stack.last_mut() {
+                parent.remaining_child_count -= 1;
+            } else if i > 0 {
+                return Err(invalid());
+            }
+            let is_dropped = stack.last().is_some_and(|parent| parent.is_dropped)
+                || (opts.drop_extras && flags & SAVED_NODE_EXTRA != 0);
+            let is_printed = if is_dropped {
+                false
+            } else {
+                let field_name = match field_index {
+                    0 => None,
+                    _ => Some(*fields.get(field_index - 1).ok_or_else(invalid)?),
+                };
+                printer.open(
+                    &mut tree_output,
+                    stack.len(),
+                    &SexpNode {
+                        kind: kinds.get(kind_index).ok_or_else(invalid)?,
+                        field_name,
+                        is_named: flags & SAVED_NODE_NAMED != 0,
+                        start: Point::new(start_row, start_column),
+                        end: Point::new(end_row, end_column),
+                    },
+                )?
+            };
+            stack.push(OpenNode {
+                remaining_child_count: child_count,
+                is_printed,
+                is_dropped,
+            });
+
+            while stack
+                .last()
+                .is_some_and(|node| node.remaining_child_count == 0)
+            {
+                let node = stack.pop().unwrap();
+                if !node.is_dropped {
+                    printer.close(&mut tree_output, stack.len(), node.is_printed)?;
+                }
+            }
+        }
+        if !stack.is_empty() {
+            return Err(invalid());
+        }
+        printer.finish(&mut tree_output)?;
+        trees.push((tree_path, tree_output));
+    }
+
+    for (tree_path, tree_output) in &trees {
+        if trees.len() > 1 {
+            writeln!(output, "; {tree_path}")?;
+        }
+        output.write_all(tree_output)?;
+    }
+    Ok(())
+}
+
+/// How `write_tree_sexp` and `write_saved_trees` print a tree.
+#[derive(Clone, Copy, Default)]
+pub struct SexpOptions<'a> {
+    pub no_ranges: bool,
+    pub compact: bool,
+    pub drop_extras: bool,
+    pub filter_node: Option<&'a str>,
+}
+
+/// Write a tree as an S-expression, in the default output format of `parse`.
+pub fn write_tree_sexp(output: &mut impl Write, tree: &Tree, opts: &SexpOptions) -> Result<()> {
+    let mut printer = SexpPrinter::new(opts);
+    let mut cursor = tree.walk();
+    // Whether each of the current node's ancestors was printed, and needs to be closed.
+    let mut stack = Vec::new();
+    let mut did_visit_children = false;
+    loop {
+        if did_visit_children {
+            if cursor.goto_next_sibling() {
+                did_visit_children = false;
+            } else if cursor.goto_parent() {
+                let is_printed = stack.pop().unwrap();
+                printer.close(output, stack.len(), is_printed)?;
+            } else {
+                break;
+            }
+            continue;
+        }
+
+        let node = cursor.node();
+        if opts.drop_extras && node.is_extra() {
+            did_visit_children = true;
+            continue;
+        }
+        let is_printed = printer.open(
+            output,
+            stack.len(),
+            &SexpNode {
+                kind: node.kind(),
+                field_name: cursor.field_name(),
+                is_named: node.is_named(),
+                start: node.start_position(),
+                end: node.end_position(),
+            },
+        )?;
+        if cursor.goto_first_child() {
+            stack.push(is_printed);
+        } else {
+            printer.close(output, stack.len(), is_printed)?;
+            did_visit_children = true;
+        }
+    }
+    printer.finish(output)
+}
+
+/// A node, as far as the S-expression output is concerned.
+struct SexpNode<'a> {
+    kind: &'a str,
+    field_name: Option<&'a str>,
+    is_named: bool,
+    start: Point,
+    end: Point,
+}
+
+/// Prints the S-expression for a tree, given each of its nodes in pre-order as it is
+/// opened, and again as it is closed, after all of its descendants.
+struct SexpPrinter<'a> {
+    opts: &'a SexpOptions<'a>,
+    needs_newline: bool,
+    // With `--filter-node`, only the subtrees rooted at nodes of the given type are
+    // printed. This is the depth of the root of the subtree that is being printed.
+    filter_root_level: Option<usize>,
+}
+
+impl<'a> SexpPrinter<'a> {
+    const fn new(opts: &'a SexpOptions<'a>) -> Self {
+        Self {
+            opts,
+            needs_newline: false,
+            filter_root_level: None,
+        }
+    }
+
+    /// Write the start of a node at the given depth, and return whether it was written,
+    /// in which case it must be closed too.
+    fn open(&mut self, output: &mut impl Write, depth: usize, node: &SexpNode) -> Result<bool> {
+        if self.filter_root_level.is_none()
+            && self.opts.filter_node.map_or(true, |kind| node.kind == kind)
+        {
+            self.filter_root_level = Some(depth);
+            if self.needs_newline {
+                output.write_all(b"\n")?;
+                self.needs_newline = false;
+            }
+        }
+        let Some(filter_root_level) = self.filter_root_level else {
+            return Ok(false);
+        };
+        if !node.is_named {
+            return Ok(false);
+        }
+
+        if self.opts.compact {
+            if self.needs_newline {
+                output.write_all(b" ")?;
+            }
+        } else {
+            if self.needs_newline {
+                output.write_all(b"\n")?;
+            }
+            for _ in filter_root_level..depth {
+                output.write_all(b"  ")?;
+            }
+        }
+        // The field name describes the node's place in its parent, which isn't printed
+        // when the node is the root of a filtered subtree.
+        if let Some(field_name) = node.field_name.filter(|_| depth != filter_root_level) {
+            write!(output, "{field_name}: ")?;
+        }
+        write!(output, "({}", node.kind)?;
+        if !self.opts.no_ranges {
+            write!(
+                output,
+                " [{}, {}] - [{}, {}]",
+                node.start.row, node.start.column, node.end.row, node.end.column
+            )?;
+        }
+        self.needs_newline = true;
+        Ok(true)
+    }
+
+    fn close(&mut self, output: &mut impl Write, depth: usize, is_printed: bool) -> Result<()> {
+        if is_printed {
+            output.write_all(b")")?;
+            self.needs_newline = true;
+        }
+        if self.filter_root_level == Some(depth) {
+            self.filter_root_level = None;
+        }
+        Ok(())
+    }
+
+    fn finish(self, output: &mut impl Write) -> Result<()> {
+        if self.needs_newline {
+            writeln!(output)?;
+        }
+        Ok(())
+    }
 }
 
 pub struct ParseFileOptions<'a> {
     pub language: Language,
     pub path: &'a Path,
     pub edits: &'a [&'a str],
+    pub diff: Option<&'a Path>,
     pub max_path_length: usize,
     pub output: ParseOutput,
     pub print_time: bool,
     pub timeout: u64,
     pub debug: bool,
-    pub debug_graph: bool,
+    pub debug_graph: Option<&'a str>,
+    pub reparse_bench: bool,
+    pub byte_range: Option<Range<usize>>,
+    pub no_ranges: bool,
+    pub compact: bool,
+    pub drop_extras: bool,
+    pub filter_node: Option<&'a str>,
+    pub named_only: bool,
+    pub retries: usize,
+    pub print_errors: bool,
+    pub schema: Option<&'a RefCell<Schema>>,
+    pub output_file: Option<&'a RefCell<io::BufWriter<fs::File>>>,
+    pub saved_tree_file: Option<&'a RefCell<io::BufWriter<fs::File>>>,
     pub cancellation_flag: Option<&'a AtomicUsize>,
     pub encoding: Option<u32>,
+    pub position_encoding: util::PositionEncoding,
+    pub count_nodes: bool,
+    pub max_nesting: bool,
+    pub max_nesting_limit: Option<usize>,
 }
 
 #[derive(Copy, Clone)]
@@ -68,6 +550,17 @@ pub struct ParseResult {
     pub successful: bool,
     pub bytes: usize,
     pub duration: Option<Duration>,
+    pub error_cost: usize,
+    pub node_counts: Option<NodeCounts>,
+    pub exceeded_nesting_limit: bool,
+}
+
+/// The size of a syntax tree, for `--count-nodes`.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct NodeCounts {
+    pub total: usize,
+    pub named: usize,
+    pub max_depth: usize,
 }
 
 pub fn parse_file_at_path(parser: &mut Parser, opts: &ParseFileOptions) -> Result<ParseResult> {
@@ -83,9 +576,29 @@ pub fn parse_file_at_path(parser: &mut Parser, opts: &ParseFileOptions) -> Resul
     // Set a timeout based on the `--time` flag.
     parser.set_timeout_micros(opts.timeout);
 
+    // Restrict the parse to the `--byte-range`, if one was passed. Because this is
+    // done with an included range, node offsets stay relative to the whole file.
+    if let Some(range) = &opts.byte_range {
+        if range.start > range.end {
+            return Err(anyhow!("Invalid byte range {}:{}", range.start, range.end));
+        }
+        let included_range = tree_sitter::Range {
+            start_byte: range.start,
+            end_byte: range.end,
+            start_point: position_for_offset(&source_code, range.start)?,
+            end_point: position_for_offset(&source_code, range.end)?,
+        };
+        parser
+            .set_included_ranges(&[included_range])
+            .map_err(|_| anyhow!("Invalid byte range {}:{}", range.start, range.end))?;
+    } else {
+        parser.set_included_ranges(&[]).unwrap();
+    }
+
     // Render an HTML graph if `--debug-graph` was passed
-    if opts.debug_graph {
-        _log_session = Some(util::log_graphs(parser, "log.html")?);
+    if let Some(debug_graph) = opts.debug_graph {
+        let graph_path = util::debug_graph_path(debug_graph, opts.path);
+        _log_session = Some(util::log_graphs(parser, &graph_path)?);
     }
     // Log to stderr if `--debug` was passed
     else if opts.debug {
@@ -104,7 +617,7 @@ pub fn parse_file_at_path(parser: &mut Parser, opts: &ParseFileOptions) -> Resul
         bom_bytes == [0xFF, 0xFE] || bom_bytes == [0xFE, 0xFF]
     }
 
-    let tree = match opts.encoding {
+    let parse = |parser: &mut Parser| match opts.encoding {
         Some(encoding) if encoding == ffi::TSInputEncodingUTF16 => {
             let source_code_utf16 = source_code
                 .chunks_exact(2)
@@ -122,100 +635,250 @@ pub fn parse_file_at_path(parser: &mut Parser, opts: &ParseFileOptions) -> Resul
         _ => parser.parse(&source_code, None),
     };
 
+    let mut tree = parse(parser);
+
+    // Parse the file again, with a fresh parser state, if `--retries` was passed and
+    // the parse failed.
+    for attempt in 1..=opts.retries {
+        if tree
+            .as_ref()
+            .is_some_and(|tree| !tree.root_node().has_error())
+        {
+            break;
+        }
+        eprintln!(
+            "Retrying {} (attempt {attempt} of {})",
+            opts.path.display(),
+            opts.retries
+        );
+        parser.reset();
+        parser.set_language(&opts.language)?;
+        tree = parse(parser);
+    }
+
     parser.stop_printing_dot_graphs();
 
     let stdout = io::stdout();
     let mut stdout = stdout.lock();
 
     if let Some(mut tree) = tree {
-        if opts.debug_graph && !opts.edits.is_empty() {
+        if opts.debug_graph.is_some() && (!opts.edits.is_empty() || opts.diff.is_some()) {
             println!("BEFORE:\n{}", String::from_utf8_lossy(&source_code));
         }
 
-        for (i, edit) in opts.edits.iter().enumerate() {
-            let edit = parse_edit_flag(&source_code, edit)?;
+        // The edits from `--diff` are applied before the ones from `--edit`.
+        let diff_edits = match opts.diff {
+            Some(diff_path) => {
+                let diff = fs::read_to_string(diff_path)
+                    .with_context(|| format!("Error reading diff file {diff_path:?}"))?;
+                parse_diff_edits(&source_code, &diff)
+                    .with_context(|| format!("Failed to apply {diff_path:?}"))?
+            }
+            None => Vec::new(),
+        };
+        let diff_edit_count = diff_edits.len();
+        let mut diff_edits = diff_edits.into_iter();
+
+        let mut reparse_duration = Duration::ZERO;
+        for i in 0..diff_edit_count + opts.edits.len() {
+            let edit = match diff_edits.next() {
+                Some(edit) => edit,
+                None => parse_edit_flag(&source_code, opts.edits[i - diff_edit_count])?,
+            };
             perform_edit(&mut tree, &mut source_code, &edit)?;
+            if opts.byte_range.is_some() {
+                parser.set_included_ranges(&tree.included_ranges()).unwrap();
+            }
+            let reparse_time = Instant::now();
             tree = parser.parse(&source_code, Some(&tree)).unwrap();
+            reparse_duration += reparse_time.elapsed();
 
-            if opts.debug_graph {
+            if opts.debug_graph.is_some() {
                 println!("AFTER {i}:\n{}", String::from_utf8_lossy(&source_code));
             }
         }
 
         let duration = time.elapsed();
         let duration_ms = duration.as_micros() as f64 / 1e3;
+
+        // Compare the time spent reparsing incrementally after the edits with the
+        // time it takes to parse the edited source code from scratch.
+        if opts.reparse_bench {
+            let full_parse_time = Instant::now();
+            parser.parse(&source_code, None);
+            let full_parse_duration = full_parse_time.elapsed();
+            writeln!(
+                &mut stdout,
+                "{:width$}\tincremental: {:>7.2} ms\tfull: {:>7.2} ms\tratio: {:.2}x",
+                opts.path.to_str().unwrap(),
+                reparse_duration.as_micros() as f64 / 1e3,
+                full_parse_duration.as_micros() as f64 / 1e3,
+                full_parse_duration.as_secs_f64() / reparse_duration.as_secs_f64(),
+                width = opts.max_path_length
+            )?;
+        }
+        if let Some(saved_tree_file) = opts.saved_tree_file {
+            write_saved_tree(&mut *saved_tree_file.borrow_mut(), opts.path, &tree)?;
+        }
+
         let mut cursor = tree.walk();
 
+        // With `--output`, the trees are written to a file, while everything else is
+        // still printed to stdout.
+        let mut output_file = opts.output_file.map(RefCell::borrow_mut);
+        let mut output: &mut dyn Write = match &mut output_file {
+            Some(file) => &mut **file,
+            None => &mut stdout,
+        };
+
         if matches!(opts.output, ParseOutput::Normal) {
-            let mut needs_newline = false;
-            let mut indent_level = 0;
+            let sexp_options = SexpOptions {
+                no_ranges: opts.no_ranges,
+                compact: opts.compact,
+                drop_extras: opts.drop_extras,
+                filter_node: opts.filter_node,
+            };
+            write_tree_sexp(&mut output, &tree, &sexp_options)?;
+        }
+
+        if matches!(opts.output, ParseOutput::Xml) {
+            write_node_xml(
+                &mut output,
+                tree.root_node(),
+                &source_code,
+                0,
+                opts.drop_extras,
+                |_| Vec::new(),
+            )?;
+            writeln!(output)?;
+        }
+
+        if matches!(opts.output, ParseOutput::Json) {
+            let mut needs_comma = false;
             let mut did_visit_children = false;
             loop {
                 let node = cursor.node();
+                // Anonymous nodes are almost always tokens, so they are skipped along with
+                // anything inside of them.
+                let skip =
+                    (opts.drop_extras && node.is_extra()) || (opts.named_only && !node.is_named());
+                if did_visit_children {
+                    if !skip {
+                        output.write_all(b"]}")?;
+                        needs_comma = true;
+                    }
+                    if cursor.goto_next_sibling() {
+                        did_visit_children = false;
+                    } else if cursor.goto_parent() {
+                        did_visit_children = true;
+                    } else {
+                        break;
+                    }
+                } else if skip {
+                    did_visit_children = true;
+                } else {
+                    if needs_comma {
+                        output.write_all(b",")?;
+                    }
+                    write!(
+                        output,
+                        "{{\"type\":{},\"named\":{}",
+                        serde_json::to_string(node.kind())?,
+                        node.is_named()
+                    )?;
+                    if let Some(field_name) = cursor.field_name() {
+                        write!(output, ",\"field\":{}", serde_json::to_string(field_name)?)?;
+                    }
+                    if !opts.no_ranges {
+                        let start = opts.position_encoding.start_position(node, &source_code);
+                        let end = opts.position_encoding.end_position(node, &source_code);
+                        write!(
+                            output,
+                            ",\"start\":{{\"row\":{},\"column\":{}}},\"end\":{{\"row\":{},\"column\":{}}}",
+                            start.row, start.column, end.row, end.column
+                        )?;
+                    }
+                    output.write_all(b",\"children\":[")?;
+                    if cursor.goto_first_child() {
+                        did_visit_children = false;
+                        needs_comma = false;
+                    } else {
+                        did_visit_children = true;
+                    }
+                }
+            }
+            cursor.reset(tree.root_node());
+            writeln!(output)?;
+        }
+
+        if matches!(opts.output, ParseOutput::Ast) {
+            // Only the named nodes are written, matching the S-expression output,
+            // along with everything needed to reconstruct that output.
+            let mut needs_comma = false;
+            let mut did_visit_children = false;
+            loop {
+                let node = cursor.node();
+                let skip = opts.drop_extras && node.is_extra();
                 let is_named = node.is_named();
                 if did_visit_children {
-                    if is_named {
-                        stdout.write_all(b")")?;
-                        needs_newline = true;
+                    if is_named && !skip {
+                        output.write_all(b"]}")?;
+                        needs_comma = true;
                     }
                     if cursor.goto_next_sibling() {
                         did_visit_children = false;
                     } else if cursor.goto_parent() {
                         did_visit_children = true;
-                        indent_level -= 1;
                     } else {
                         break;
                     }
+                } else if skip {
+                    did_visit_children = true;
                 } else {
                     if is_named {
-                        if needs_newline {
-                            stdout.write_all(b"\n")?;
-                        }
-                        for _ in 0..indent_level {
-                            stdout.write_all(b"  ")?;
-                        }
-                        let start = node.start_position();
-                        let end = node.end_position();
-                        if let Some(field_name) = cursor.field_name() {
-                            write!(&mut stdout, "{field_name}: ")?;
+                        if needs_comma {
+                            output.write_all(b",")?;
                         }
+                        let start = opts.position_encoding.start_position(node, &source_code);
+                        let end = opts.position_encoding.end_position(node, &source_code);
                         write!(
-                            &mut stdout,
-                            "({} [{}, {}] - [{}, {}]",
-                            node.kind(),
+                            output,
+                            "{{\"type\":{},\"field\":{},\"start_byte\":{},\"end_byte\":{},\"start\":{{\"row\":{},\"column\":{}}},\"end\":{{\"row\":{},\"column\":{}}},\"extra\":{},\"error\":{},\"missing\":{},\"children\":[",
+                            serde_json::to_string(node.kind())?,
+                            serde_json::to_string(&cursor.field_name())?,
+                            node.start_byte(),
+                            node.end_byte(),
                             start.row,
                             start.column,
                             end.row,
-                            end.column
+                            end.column,
+                            node.is_extra(),
+                            node.is_error(),
+                            node.is_missing(),
                         )?;
-                        needs_newline = true;
                     }
                     if cursor.goto_first_child() {
                         did_visit_children = false;
-                        indent_level += 1;
+                        if is_named {
+                            needs_comma = false;
+                        }
                     } else {
                         did_visit_children = true;
                     }
                 }
             }
             cursor.reset(tree.root_node());
-            println!();
+            writeln!(output)?;
         }
 
-        if matches!(opts.output, ParseOutput::Xml) {
-            let mut needs_newline = false;
+        if matches!(opts.output, ParseOutput::Cst) {
             let mut indent_level = 0;
             let mut did_visit_children = false;
-            let mut tags: Vec<&str> = Vec::new();
             loop {
                 let node = cursor.node();
-                let is_named = node.is_named();
+                let skip =
+                    (opts.drop_extras && node.is_extra()) || (opts.named_only && !node.is_named());
                 if did_visit_children {
-                    if is_named {
-                        let tag = tags.pop();
-                        writeln!(&mut stdout, "</{}>", tag.expect("there is a tag"))?;
-                        needs_newline = true;
-                    }
                     if cursor.goto_next_sibling() {
                         did_visit_children = false;
                     } else if cursor.goto_parent() {
@@ -224,37 +887,43 @@ pub fn parse_file_at_path(parser: &mut Parser, opts: &ParseFileOptions) -> Resul
                     } else {
                         break;
                     }
+                } else if skip {
+                    did_visit_children = true;
                 } else {
-                    if is_named {
-                        if needs_newline {
-                            stdout.write_all(b"\n")?;
-                        }
-                        for _ in 0..indent_level {
-                            stdout.write_all(b"  ")?;
-                        }
-                        write!(&mut stdout, "<{}", node.kind())?;
-                        if let Some(field_name) = cursor.field_name() {
-                            write!(&mut stdout, " type=\"{field_name}\"")?;
-                        }
-                        write!(&mut stdout, ">")?;
-                        tags.push(node.kind());
-                        needs_newline = true;
+                    for _ in 0..indent_level {
+                        output.write_all(b"  ")?;
+                    }
+                    if let Some(field_name) = cursor.field_name() {
+                        write!(output, "{field_name}: ")?;
+                    }
+                    if node.is_named() {
+                        write!(output, "{}", node.kind())?;
+                    } else {
+                        write!(output, "{:?}", node.kind())?;
+                    }
+                    if !opts.no_ranges {
+                        let start = node.start_position();
+                        let end = node.end_position();
+                        write!(
+                            output,
+                            " [{}, {}] - [{}, {}]",
+                            start.row, start.column, end.row, end.column
+                        )?;
                     }
+                    if node.child_count() == 0 {
+                        let text = String::from_utf8_lossy(&source_code[node.byte_range()]);
+                        write!(output, " {text:?}")?;
+                    }
+                    writeln!(output)?;
                     if cursor.goto_first_child() {
                         did_visit_children = false;
                         indent_level += 1;
                     } else {
                         did_visit_children = true;
-                        let start = node.start_byte();
-                        let end = node.end_byte();
-                        let value =
-                            std::str::from_utf8(&source_code[start..end]).expect("has a string");
-                        write!(&mut stdout, "{}", html_escape::encode_text(value))?;
                     }
                 }
             }
             cursor.reset(tree.root_node());
-            println!();
         }
 
         if matches!(opts.output, ParseOutput::Dot) {
@@ -276,7 +945,11 @@ pub fn parse_file_at_path(parser: &mut Parser, opts: &ParseFileOptions) -> Resul
             }
         }
 
-        if first_error.is_some() || opts.print_time {
+        if let Some(schema) = opts.schema {
+            schema.borrow_mut().record(&tree);
+        }
+
+        if (first_error.is_some() && opts.schema.is_none()) || opts.print_time {
             write!(
                 &mut stdout,
                 "{:width$}\t{duration_ms:>7.2} ms\t{:>6} bytes/ms",
@@ -310,11 +983,53 @@ pub fn parse_file_at_path(parser: &mut Parser, opts: &ParseFileOptions) -> Resul
             writeln!(&mut stdout)?;
         }
 
+        if opts.print_errors && first_error.is_some() {
+            write_error_diagnostics(&mut stdout, &tree, &source_code, opts.path)?;
+        }
+
+        let counts = (opts.count_nodes || opts.max_nesting).then(|| count_nodes(&tree));
+        let node_counts = counts.filter(|_| opts.count_nodes);
+        if let Some(counts) = node_counts {
+            writeln!(
+                &mut stdout,
+                "{:width$}\tnodes: {}\tnamed: {}\tdepth: {}",
+                opts.path.to_str().unwrap(),
+                counts.total,
+                counts.named,
+                counts.max_depth,
+                width = opts.max_path_length
+            )?;
+        }
+
+        // Code that recurses over the tree needs a stack frame for each level of nesting.
+        let mut exceeded_nesting_limit = false;
+        if let Some(depth) = counts.filter(|_| opts.max_nesting).map(|c| c.max_depth) {
+            write!(
+                &mut stdout,
+                "{:width$}\tmax nesting: {depth}",
+                opts.path.to_str().unwrap(),
+                width = opts.max_path_length
+            )?;
+            if let Some(limit) = opts.max_nesting_limit.filter(|limit| depth > *limit) {
+                write!(&mut stdout, "\texceeds the limit of {limit}")?;
+                exceeded_nesting_limit = true;
+            }
+            writeln!(&mut stdout)?;
+        }
+
         return Ok(ParseResult {
             successful: first_error.is_none(),
             bytes: source_code.len(),
             duration: Some(duration),
+            error_cost: error_cost(&tree),
+            node_counts,
+            exceeded_nesting_limit,
         });
+    } else if util::ran_out_of_wasm_fuel(parser) {
+        return Err(anyhow!(
+            "Parsing {} ran out of wasm fuel. Pass a larger --wasm-fuel to allow more work",
+            opts.path.display()
+        ));
     } else if opts.print_time {
         let duration = time.elapsed();
         let duration_ms = duration.as_micros() as f64 / 1e3;
@@ -326,13 +1041,397 @@ pub fn parse_file_at_path(parser: &mut Parser, opts: &ParseFileOptions) -> Resul
         )?;
     }
 
+    // A parse that timed out or was cancelled costs as much as a single ERROR node
+    // spanning the whole file.
     Ok(ParseResult {
         successful: false,
         bytes: source_code.len(),
         duration: None,
+        error_cost: 1 + source_code.len(),
+        node_counts: None,
+        exceeded_nesting_limit: false,
     })
 }
 
+/// Write the named nodes of the given subtree as nested XML elements, containing the
+/// text of the leaf nodes. Each element can be given extra attributes by `attributes`.
+pub fn write_node_xml<'tree>(
+    stdout: &mut impl Write,
+    node: Node<'tree>,
+    source_code: &[u8],
+    mut indent_level: usize,
+    drop_extras: bool,
+    attributes: impl Fn(Node<'tree>) -> Vec<(&'static str, String)>,
+) -> Result<()> {
+    let mut cursor = node.walk();
+    let mut needs_newline = false;
+    let mut did_visit_children = false;
+    let mut tags: Vec<&str> = Vec::new();
+    loop {
+        let node = cursor.node();
+        let skip = drop_extras && node.is_extra();
+        let is_named = node.is_named();
+        if did_visit_children {
+            if is_named && !skip {
+                let tag = tags.pop();
+                writeln!(stdout, "</{}>", tag.expect("there is a tag"))?;
+                needs_newline = true;
+            }
+            if cursor.goto_next_sibling() {
+                did_visit_children = false;
+            } else if cursor.goto_parent() {
+                did_visit_children = true;
+                indent_level -= 1;
+            } else {
+                break;
+            }
+        } else if skip {
+            did_visit_children = true;
+        } else {
+            if is_named {
+                if needs_newline {
+                    stdout.write_all(b"\n")?;
+                }
+                for _ in 0..indent_level {
+                    stdout.write_all(b"  ")?;
+                }
+                write!(stdout, "<{}", node.kind())?;
+                if let Some(field_name) = cursor.field_name() {
+                    write!(stdout, " type=\"{field_name}\"")?;
+                }
+                for (name, value) in attributes(node) {
+                    write!(
+                        stdout,
+                        " {name}=\"{}\"",
+                        html_escape::encode_double_quoted_attribute(&value)
+                    )?;
+                }
+                write!(stdout, ">")?;
+                tags.push(node.kind());
+                needs_newline = true;
+            }
+            if cursor.goto_first_child() {
+                did_visit_children = false;
+                indent_level += 1;
+            } else {
+                did_visit_children = true;
+                let start = node.start_byte();
+                let end = node.end_byte();
+                let value = std::str::from_utf8(&source_code[start..end]).expect("has a string");
+                write!(stdout, "{}", html_escape::encode_text(value))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Write the nodes of the given subtree and the edges between them as DOT statements.
+/// The ids of the nodes start with `id_prefix`, so that several subtrees can be drawn
+/// in the same graph. Each node is labeled with its kind, and can be given extra
+/// attributes by `attributes`, including another label.
+pub fn write_node_dot_graph<'tree>(
+    stdout: &mut impl Write,
+    node: Node<'tree>,
+    id_prefix: &str,
+    indent_level: usize,
+    attributes: impl Fn(Node<'tree>) -> Vec<(&'static str, String)>,
+) -> Result<()> {
+    let indent = "  ".repeat(indent_level);
+    let mut cursor = node.walk();
+    let mut parent_ids = Vec::new();
+    loop {
+        let node = cursor.node();
+        let dot_id = format!("{id_prefix}{}", node.id());
+        let mut attributes = attributes(node);
+        if !attributes.iter().any(|(name, _)| *name == "label") {
+            let kind = if node.is_named() {
+                node.kind().to_string()
+            } else {
+                format!("\"{}\"", node.kind())
+            };
+            attributes.insert(0, ("label", kind));
+        }
+        let attributes = attributes
+            .iter()
+            .map(|(name, value)| format!("{name}=\"{}\"", escape_dot_string(value)))
+            .collect::<Vec<_>>();
+        writeln!(stdout, "{indent}{dot_id} [{}];", attributes.join(", "))?;
+        if let Some(parent_id) = parent_ids.last() {
+            write!(stdout, "{indent}{id_prefix}{parent_id} -> {dot_id}")?;
+            if let Some(field_name) = cursor.field_name() {
+                write!(stdout, " [label=\"{field_name}\"]")?;
+            }
+            writeln!(stdout, ";")?;
+        }
+
+        if cursor.goto_first_child() {
+            parent_ids.push(node.id());
+            continue;
+        }
+        loop {
+            if cursor.goto_next_sibling() {
+                break;
+            }
+            if !cursor.goto_parent() {
+                return Ok(());
+            }
+            parent_ids.pop();
+        }
+    }
+}
+
+/// Escape a string for use inside a quoted DOT string, where a newline starts a new
+/// line of the label.
+pub fn escape_dot_string(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// The number of ERROR and MISSING nodes in the tree.
+#[must_use]
+pub fn count_errors(tree: &Tree) -> usize {
+    let mut count = 0;
+    let mut cursor = tree.walk();
+    loop {
+        let node = cursor.node();
+        if node.is_error() || node.is_missing() {
+            count += 1;
+        }
+        if node.has_error() && cursor.goto_first_child() {
+            continue;
+        }
+        while !cursor.goto_next_sibling() {
+            if !cursor.goto_parent() {
+                return count;
+            }
+        }
+    }
+}
+
+/// Count the nodes in the tree, and find the depth of its deepest node, counting the
+/// root node as depth 1.
+#[must_use]
+pub fn count_nodes(tree: &Tree) -> NodeCounts {
+    let mut counts = NodeCounts::default();
+    let mut cursor = tree.walk();
+    let mut depth = 1;
+    loop {
+        let node = cursor.node();
+        counts.total += 1;
+        if node.is_named() {
+            counts.named += 1;
+        }
+        counts.max_depth = counts.max_depth.max(depth);
+        if cursor.goto_first_child() {
+            depth += 1;
+            continue;
+        }
+        while !cursor.goto_next_sibling() {
+            if !cursor.goto_parent() {
+                return counts;
+            }
+            depth -= 1;
+        }
+    }
+}
+
+/// A score for how badly a tree failed to parse, for comparing grammars: each ERROR
+/// node costs 1 plus the number of bytes that it spans, and each MISSING node costs 1.
+/// The ERROR nodes inside of another ERROR node are not counted again.
+#[must_use]
+pub fn error_cost(tree: &Tree) -> usize {
+    let mut cost = 0;
+    let mut cursor = tree.walk();
+    loop {
+        let node = cursor.node();
+        if node.is_error() {
+            cost += 1 + node.byte_range().len();
+        } else if node.is_missing() {
+            cost += 1;
+        } else if node.has_error() && cursor.goto_first_child() {
+            continue;
+        }
+        while !cursor.goto_next_sibling() {
+            if !cursor.goto_parent() {
+                return cost;
+            }
+        }
+    }
+}
+
+/// The node types observed in a set of syntax trees, along with the types of the
+/// nodes that appeared under each of them, in the format of `node-types.json`.
+///
+/// Unlike `node-types.json`, this describes what actually occurs in the parsed
+/// code rather than everything the grammar permits. ERROR nodes, MISSING nodes and
+/// extras are left out.
+#[derive(Default)]
+pub struct Schema {
+    nodes: BTreeMap<(String, bool), NodeSchema>,
+}
+
+#[derive(Default)]
+struct NodeSchema {
+    count: usize,
+    fields: BTreeMap<&'static str, ChildSchema>,
+    children: ChildSchema,
+}
+
+#[derive(Default)]
+struct ChildSchema {
+    /// The number of parent nodes under which at least one of these children appeared.
+    count: usize,
+    multiple: bool,
+    types: BTreeSet<(String, bool)>,
+}
+
+impl ChildSchema {
+    fn record(&mut self, types: Vec<(String, bool)>) {
+        self.count += 1;
+        self.multiple |= types.len() > 1;
+        self.types.extend(types);
+    }
+
+    fn to_json(&self, parent_count: usize) -> serde_json::Value {
+        serde_json::json!({
+            "multiple": self.multiple,
+            "required": self.count == parent_count,
+            "types": self
+                .types
+                .iter()
+                .map(|(kind, named)| serde_json::json!({ "type": kind, "named": named }))
+                .collect::<Vec<_>>(),
+        })
+    }
+}
+
+impl Schema {
+    pub fn record(&mut self, tree: &Tree) {
+        let mut cursor = tree.walk();
+        let mut stack = vec![tree.root_node()];
+        while let Some(node) = stack.pop() {
+            let entry = self
+                .nodes
+                .entry((node.kind().to_string(), node.is_named()))
+                .or_default();
+            entry.count += 1;
+
+            let mut fields = BTreeMap::<_, Vec<_>>::new();
+            let mut children = Vec::new();
+            for (i, child) in node.children(&mut cursor).enumerate() {
+                if child.is_error() || child.is_missing() || child.is_extra() {
+                    continue;
+                }
+                let kind = (child.kind().to_string(), child.is_named());
+                if let Some(field_name) = node.field_name_for_child(i as u32) {
+                    fields.entry(field_name).or_default().push(kind);
+                } else if child.is_named() {
+                    children.push(kind);
+                }
+                stack.push(child);
+            }
+
+            for (field_name, types) in fields {
+                entry.fields.entry(field_name).or_default().record(types);
+            }
+            if !children.is_empty() {
+                entry.children.record(children);
+            }
+        }
+    }
+
+    #[must_use]
+    pub fn to_json(&self) -> serde_json::Value {
+        self.nodes
+            .iter()
+            .map(|((kind, named), node)| {
+                let mut json = serde_json::json!({ "type": kind, "named": named });
+                if !node.fields.is_empty() || node.children.count > 0 {
+                    json["fields"] = node
+                        .fields
+                        .iter()
+                        .map(|(name, field)| (name.to_string(), field.to_json(node.count)))
+                        .collect::<serde_json::Map<_, _>>()
+                        .into();
+                }
+                if node.children.count > 0 {
+                    json["children"] = node.children.to_json(node.count);
+                }
+                json
+            })
+            .collect()
+    }
+}
+
+/// Print each ERROR and MISSING node in the tree like a compiler diagnostic: its
+/// location, followed by the source line where it starts with a caret underline
+/// beneath the erroneous text. The contents of ERROR nodes are not reported separately.
+pub fn write_error_diagnostics(
+    stdout: &mut impl Write,
+    tree: &Tree,
+    source_code: &[u8],
+    path: &Path,
+) -> Result<()> {
+    let lines = source_code.split(|b| *b == b'\n').collect::<Vec<_>>();
+    let mut cursor = tree.walk();
+    loop {
+        let node = cursor.node();
+        if node.is_error() || node.is_missing() {
+            let start = node.start_position();
+            let message = if node.is_missing() {
+                format!("missing {}", node.kind().replace('\n', "\\n"))
+            } else {
+                let text = String::from_utf8_lossy(&source_code[node.byte_range()]);
+                let text = text.lines().next().unwrap_or_default();
+                format!("unexpected `{text}`")
+            };
+            writeln!(
+                stdout,
+                "{}:{}:{}: error: {message}",
+                path.display(),
+                start.row + 1,
+                start.column + 1
+            )?;
+
+            let line = lines.get(start.row).copied().unwrap_or_default();
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            let column = start.column.min(line.len());
+            let underline_end = if node.end_position().row == start.row {
+                node.end_position().column.min(line.len())
+            } else {
+                line.len()
+            };
+            // Keep tabs in the padding, so that the caret lines up with the source.
+            let padding = String::from_utf8_lossy(&line[..column])
+                .chars()
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect::<String>();
+            let width = String::from_utf8_lossy(&line[column..underline_end])
+                .chars()
+                .count()
+                .max(1);
+            let gutter = (start.row + 1).to_string();
+            writeln!(stdout, "{} |", " ".repeat(gutter.len()))?;
+            writeln!(stdout, "{gutter} | {}", String::from_utf8_lossy(line))?;
+            writeln!(
+                stdout,
+                "{} | {padding}{}",
+                " ".repeat(gutter.len()),
+                "^".repeat(width)
+            )?;
+        } else if node.has_error() && cursor.goto_first_child() {
+            continue;
+        }
+
+        while !cursor.goto_next_sibling() {
+            if !cursor.goto_parent() {
+                return Ok(());
+            }
+        }
+    }
+}
+
 pub fn perform_edit(tree: &mut Tree, input: &mut Vec<u8>, edit: &Edit) -> Result<InputEdit> {
     let start_byte = edit.position;
     let old_end_byte = edit.position + edit.deleted_length;
@@ -394,6 +1493,140 @@ fn parse_edit_flag(source_code: &[u8], flag: &str) -> Result<Edit> {
     })
 }
 
+/// Translate the hunks of a unified diff of the given source code into edits, which
+/// must be applied in order. Each hunk's context and removed lines must match the
+/// source code.
+pub fn parse_diff_edits(source_code: &[u8], diff: &str) -> Result<Vec<Edit>> {
+    let mut edits = Vec::new();
+    let mut source_code = source_code.to_vec();
+    let mut lines = diff.split_inclusive('\n').peekable();
+    while let Some(line) = lines.next() {
+        let Some(hunk_header) = line.strip_prefix("@@ ") else {
+            continue;
+        };
+        let header = line.trim_end();
+        let invalid_header = || anyhow!("Invalid hunk header {header:?}");
+        let parse_range = |prefix: char| -> Result<(usize, usize)> {
+            let range = hunk_header
+                .split(' ')
+                .find_map(|range| range.strip_prefix(prefix))
+                .ok_or_else(invalid_header)?;
+            let (start, count) = match range.split_once(',') {
+                Some((start, count)) => (start.parse::<usize>(), count.parse::<usize>()),
+                None => (range.parse::<usize>(), Ok(1)),
+            };
+            match (start, count) {
+                (Ok(start), Ok(count)) => Ok((start, count)),
+                _ => Err(invalid_header()),
+            }
+        };
+        let (_, mut old_row_count) = parse_range('-')?;
+        let (start_row, mut new_row_count) = parse_range('+')?;
+        let row_count = new_row_count;
+
+        // Earlier hunks have already been applied, so the new file's line numbers
+        // refer to the edited source code. An empty range starts after its line.
+        let row = if row_count == 0 {
+            start_row
+        } else {
+            start_row.saturating_sub(1)
+        };
+        let mut offset = if row == 0 {
+            0
+        } else {
+            memchr::memchr_iter(b'\n', &source_code)
+                .nth(row - 1)
+                .map(|newline| newline + 1)
+                .ok_or_else(|| anyhow!("The diff does not apply: there is no line {}", row + 1))?
+        };
+        let mut edit = Edit {
+            position: offset,
+            deleted_length: 0,
+            inserted_text: Vec::new(),
+        };
+
+        // The hunk ends after the numbers of lines in its header, and any
+        // `\ No newline at end of file` marker after its last line.
+        while old_row_count > 0
+            || new_row_count > 0
+            || lines.peek().is_some_and(|line| line.starts_with('\\'))
+        {
+            let Some(line) = lines.next() else {
+                return Err(anyhow!("The hunk {header:?} ends before all of its lines"));
+            };
+            // Editors often strip the trailing space from an empty context line.
+            let (kind, mut text) = match line {
+                "\n" | "\r\n" => (" ", line),
+                _ => line.split_at(line.len().min(1)),
+            };
+            if kind == "\\" {
+                continue;
+            }
+            // A line that lacks a newline at the end of the file is followed by a
+            // `\ No newline at end of file` marker.
+            if lines.peek().is_some_and(|next| next.starts_with('\\')) {
+                text = text.strip_suffix('\n').unwrap_or(text);
+            }
+            let (removes, adds) = match kind {
+                " " => (true, true),
+                "-" => (true, false),
+                "+" => (false, true),
+                _ => return Err(anyhow!("Invalid line in hunk: {:?}", line.trim_end())),
+            };
+            if (removes && old_row_count == 0) || (adds && new_row_count == 0) {
+                return Err(anyhow!(
+                    "The hunk {header:?} has more lines than its header says"
+                ));
+            }
+            old_row_count -= usize::from(removes);
+            new_row_count -= usize::from(adds);
+
+            if kind == "+" {
+                edit.inserted_text.extend_from_slice(text.as_bytes());
+                continue;
+            }
+            let start = offset + edit.deleted_length;
+            if !source_code
+                .get(start..)
+                .is_some_and(|rest| rest.starts_with(text.as_bytes()))
+            {
+                return Err(anyhow!(
+                    "The diff does not apply: expected {:?} at byte {start}",
+                    text
+                ));
+            }
+            if kind == "-" {
+                edit.deleted_length += text.len();
+                continue;
+            }
+
+            // A context line ends the current run of changes.
+            offset = apply_diff_edit(&mut source_code, &mut edits, edit) + text.len();
+            edit = Edit {
+                position: offset,
+                deleted_length: 0,
+                inserted_text: Vec::new(),
+            };
+        }
+        apply_diff_edit(&mut source_code, &mut edits, edit);
+    }
+    Ok(edits)
+}
+
+// Apply the edit to the source code, if it changes anything, and return the offset
+// at which the edited text ends.
+fn apply_diff_edit(source_code: &mut Vec<u8>, edits: &mut Vec<Edit>, edit: Edit) -> usize {
+    let end = edit.position + edit.inserted_text.len();
+    if edit.deleted_length > 0 || !edit.inserted_text.is_empty() {
+        source_code.splice(
+            edit.position..edit.position + edit.deleted_length,
+            edit.inserted_text.iter().copied(),
+        );
+        edits.push(edit);
+    }
+    end
+}
+
 pub fn offset_for_position(input: &[u8], position: Point) -> Result<usize> {
     let mut row = 0;
     let mut offset = 0;
@@ -439,3 +1672,118 @@ pub fn position_for_offset(input: &[u8], offset: usize) -> Result<Point> {
     };
     Ok(result)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply_diff(source: &str, diff: &str) -> Result<String> {
+        let mut source_code = source.as_bytes().to_vec();
+        for edit in parse_diff_edits(source.as_bytes(), diff)? {
+            source_code.splice(
+                edit.position..edit.position + edit.deleted_length,
+                edit.inserted_text,
+            );
+        }
+        Ok(String::from_utf8(source_code).unwrap())
+    }
+
+    #[test]
+    fn test_parse_diff_edits_with_multiple_hunks() {
+        let source = "a 1\na 2\na 3\na 4\na 5\na 6\na 7\na 8\n";
+        let diff = concat!(
+            "--- a/in.txt\n",
+            "+++ b/in.txt\n",
+            "@@ -1,2 +1,2 @@\n",
+            "-a 1\n",
+            "+b 1\n",
+            " a 2\n",
+            "@@ -6,3 +6,4 @@\n",
+            " a 6\n",
+            "+b 6\n",
+            " a 7\n",
+            "-a 8\n",
+            "+b 8\n",
+        );
+        assert_eq!(
+            apply_diff(source, diff).unwrap(),
+            "b 1\na 2\na 3\na 4\na 5\na 6\nb 6\na 7\nb 8\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_diff_edits_without_newline_at_end_of_file() {
+        let diff = concat!(
+            "@@ -1,2 +1,2 @@\n",
+            " a 1\n",
+            "-a 2\n",
+            "\\ No newline at end of file\n",
+            "+b 2\n",
+            "\\ No newline at end of file\n",
+        );
+        assert_eq!(apply_diff("a 1\na 2", diff).unwrap(), "a 1\nb 2");
+
+        let diff = concat!(
+            "@@ -1 +1 @@\n",
+            "-a 1\n",
+            "\\ No newline at end of file\n",
+            "+a 1\n",
+        );
+        assert_eq!(apply_diff("a 1", diff).unwrap(), "a 1\n");
+    }
+
+    #[test]
+    fn test_parse_diff_edits_with_empty_context_lines() {
+        // The trailing space of the empty context line was stripped.
+        let diff = concat!(
+            "@@ -1,4 +1,5 @@\n",
+            " a 1\n",
+            "\n",
+            " a 3\n",
+            " a 4\n",
+            "+a 5\n",
+        );
+        assert_eq!(
+            apply_diff("a 1\n\na 3\na 4\n", diff).unwrap(),
+            "a 1\n\na 3\na 4\na 5\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_diff_edits_with_invalid_hunks() {
+        let source = "a 1\na 2\n";
+        for (diff, message) in [
+            ("@@ -1 @@\n-a 1\n", "Invalid hunk header \"@@ -1 @@\""),
+            (
+                "@@ -x +1 @@\n-a 1\n+b 1\n",
+                "Invalid hunk header \"@@ -x +1 @@\"",
+            ),
+            (
+                "@@ -1,2 +1,y @@\n",
+                "Invalid hunk header \"@@ -1,2 +1,y @@\"",
+            ),
+            (
+                "@@ -1,2 +1,2 @@\n a 1\n",
+                "The hunk \"@@ -1,2 +1,2 @@\" ends before all of its lines",
+            ),
+            (
+                "@@ -1 +1 @@\n-a 1\n-a 2\n",
+                "The hunk \"@@ -1 +1 @@\" has more lines than its header says",
+            ),
+            (
+                "@@ -1,2 +1,2 @@\n a 1\n*a 2\n",
+                "Invalid line in hunk: \"*a 2\"",
+            ),
+            (
+                "@@ -1 +1 @@\n-a 2\n+b 2\n",
+                "The diff does not apply: expected \"a 2\\n\" at byte 0",
+            ),
+        ] {
+            assert_eq!(
+                apply_diff(source, diff).unwrap_err().to_string(),
+                message,
+                "diff: {diff:?}"
+            );
+        }
+    }
+}
@@ -1,97 +1,281 @@
-use crate::query_testing;
-use anyhow::{Context, Result};
+use crate::{parse, query_testing, util};
+use anyhow::{anyhow, Context, Result};
 use std::{
+    collections::{HashMap, HashSet},
     fs,
-    io::{self, Write},
+    io::{self, Read, Write},
     ops::Range,
-    path::Path,
+    path::{Path, PathBuf},
     time::Instant,
 };
-use tree_sitter::{Language, Parser, Point, Query, QueryCursor};
+use tree_sitter::{
+    Language, Node, Parser, Point, Query, QueryCursor, QueryMatch, QueryPredicateArg, QueryProperty,
+};
+
+pub struct QueryFileOptions {
+    pub ordered_captures: bool,
+    pub byte_range: Option<Range<usize>>,
+    pub point_range: Option<Range<Point>>,
+    pub should_test: bool,
+    pub quiet: bool,
+    pub print_time: bool,
+    pub time_json: bool,
+    pub dot: bool,
+    pub max_start_depth: Option<u32>,
+    pub context: usize,
+    pub capture_text: Option<String>,
+    pub limit: Option<usize>,
+    pub limit_total: Option<usize>,
+    pub any: bool,
+    pub named_only: bool,
+    pub group_by: Option<String>,
+    pub xml: bool,
+    pub properties: bool,
+    pub report_unmatched: bool,
+    pub position_encoding: util::PositionEncoding,
+    pub sort: bool,
+}
 
-#[allow(clippy::too_many_arguments)]
 pub fn query_files_at_paths(
     language: &Language,
-    paths: Vec<String>,
-    query_path: &Path,
-    ordered_captures: bool,
-    byte_range: Option<Range<usize>>,
-    point_range: Option<Range<Point>>,
-    should_test: bool,
-    quiet: bool,
-    print_time: bool,
+    mut paths: Vec<String>,
+    query_paths: &[PathBuf],
+    opts: &QueryFileOptions,
 ) -> Result<()> {
+    let QueryFileOptions {
+        ordered_captures,
+        should_test,
+        quiet,
+        print_time,
+        dot,
+        max_start_depth,
+        context,
+        limit,
+        limit_total,
+        any,
+        named_only,
+        xml,
+        properties,
+        report_unmatched,
+        position_encoding,
+        sort,
+        ..
+    } = *opts;
+
     let stdout = io::stdout();
     let mut stdout = stdout.lock();
 
-    let query_source = fs::read_to_string(query_path)
-        .with_context(|| format!("Error reading query file {query_path:?}"))?;
-    let query = Query::new(language, &query_source).with_context(|| "Query compilation failed")?;
+    if sort {
+        paths.sort();
+    }
+
+    let mut query_source = String::new();
+    for query_path in query_paths {
+        query_source += &read_query(query_path)?;
+    }
+
+    // When testing quietly, only the exit code reports whether the query compiled
+    // and all of its assertions passed.
+    let silent = should_test && quiet;
+
+    let query = Query::new(language, &query_source).map_err(|error| {
+        if silent {
+            anyhow!("")
+        } else {
+            anyhow::Error::from(error).context("Query compilation failed")
+        }
+    })?;
 
     let mut query_cursor = QueryCursor::new();
-    if let Some(range) = byte_range {
+    if let Some(range) = opts.byte_range.clone() {
         query_cursor.set_byte_range(range);
     }
-    if let Some(range) = point_range {
+    if let Some(range) = opts.point_range.clone() {
         query_cursor.set_point_range(range);
     }
+    query_cursor.set_max_start_depth(max_start_depth);
+
+    // With `--capture-text`, only the text of the given capture is printed.
+    let raw_capture_index = opts
+        .capture_text
+        .as_deref()
+        .map(|name| {
+            query
+                .capture_index_for_name(name)
+                .ok_or_else(|| anyhow!("Capture @{name} does not appear in the query"))
+        })
+        .transpose()?;
+
+    // With `--group-by`, the matches are counted by the text of the given capture,
+    // and only the counts are printed, once all of the files have been queried.
+    let group_capture_index = opts
+        .group_by
+        .as_deref()
+        .map(|name| {
+            query
+                .capture_index_for_name(name)
+                .ok_or_else(|| anyhow!("Capture @{name} does not appear in the query"))
+        })
+        .transpose()?;
+    let mut group_counts = HashMap::<String, usize>::new();
+    let prints_matches = raw_capture_index.is_none() && group_capture_index.is_none() && !xml;
+
+    // With `--report-unmatched`, the patterns that never matched in any file are
+    // listed once all of the files have been queried.
+    let mut pattern_match_counts = vec![0_usize; query.pattern_count()];
 
     let mut parser = Parser::new();
     parser.set_language(language)?;
 
+    // With `--captures`, the limits apply to the number of captures instead of matches.
+    let mut total_match_count = 0;
     for path in paths {
+        if limit_total.is_some_and(|limit_total| total_match_count >= limit_total) {
+            break;
+        }
+        let max_match_count = limit
+            .unwrap_or(usize::MAX)
+            .min(limit_total.map_or(usize::MAX, |limit_total| limit_total - total_match_count));
+
+        // With `--any`, only the location of the first match in any file is printed.
+        if any {
+            let source_code =
+                fs::read(&path).with_context(|| format!("Error reading source file {path:?}"))?;
+            let tree = parser.parse(&source_code, None).unwrap();
+            let first_match = query_cursor
+                .matches(&query, tree.root_node(), source_code.as_slice())
+                .next();
+            if let Some(m) = first_match {
+                let start = m
+                    .captures
+                    .iter()
+                    .map(|capture| capture.node.start_position())
+                    .min()
+                    .unwrap_or_default();
+                if !quiet {
+                    writeln!(&mut stdout, "{path}:{}:{}", start.row + 1, start.column + 1)?;
+                }
+                return Ok(());
+            }
+            continue;
+        }
+
         let mut results = Vec::new();
 
-        writeln!(&mut stdout, "{path}")?;
+        if dot {
+            writeln!(&mut stdout, "digraph tree {{")?;
+            writeln!(
+                &mut stdout,
+                "  label=\"{}\";",
+                parse::escape_dot_string(&path)
+            )?;
+        } else if xml {
+            writeln!(
+                &mut stdout,
+                "<file path=\"{}\">",
+                html_escape::encode_double_quoted_attribute(&path)
+            )?;
+        } else if !silent && prints_matches {
+            writeln!(&mut stdout, "{path}")?;
+        }
 
         let source_code =
             fs::read(&path).with_context(|| format!("Error reading source file {path:?}"))?;
         let tree = parser.parse(&source_code, None).unwrap();
 
         let start = Instant::now();
-        if ordered_captures {
-            for (mat, capture_index) in
-                query_cursor.captures(&query, tree.root_node(), source_code.as_slice())
+        let mut sorted_output = Vec::new();
+        if ordered_captures && !dot && prints_matches {
+            for (mat, capture_index) in query_cursor
+                .captures(&query, tree.root_node(), source_code.as_slice())
+                .take(max_match_count)
             {
+                total_match_count += 1;
+                pattern_match_counts[mat.pattern_index] += 1;
                 let capture = mat.captures[capture_index];
+                let mut output = Vec::new();
                 let capture_name = &query.capture_names()[capture.index as usize];
-                if !quiet {
+                if !quiet && (!named_only || capture.node.is_named()) {
                     writeln!(
-                        &mut stdout,
+                        &mut output,
                         "    pattern: {:>2}, capture: {} - {capture_name}, start: {}, end: {}, text: `{}`",
                         mat.pattern_index,
                         capture.index,
-                        capture.node.start_position(),
-                        capture.node.end_position(),
+                        position_encoding.start_position(capture.node, &source_code),
+                        position_encoding.end_position(capture.node, &source_code),
                         capture.node.utf8_text(&source_code).unwrap_or("")
                     )?;
+                    if context > 0 {
+                        write_context_lines(
+                            &mut output,
+                            &source_code,
+                            capture.node.start_position().row,
+                            context,
+                        )?;
+                    }
                 }
                 results.push(query_testing::CaptureInfo {
                     name: (*capture_name).to_string(),
                     start: capture.node.start_position(),
                     end: capture.node.end_position(),
                 });
+                if sort {
+                    sorted_output.push((capture.node.start_byte(), output));
+                } else {
+                    stdout.write_all(&output)?;
+                }
             }
         } else {
-            for m in query_cursor.matches(&query, tree.root_node(), source_code.as_slice()) {
-                if !quiet {
-                    writeln!(&mut stdout, "  pattern: {}", m.pattern_index)?;
+            for (i, m) in query_cursor
+                .matches(&query, tree.root_node(), source_code.as_slice())
+                .take(max_match_count)
+                .enumerate()
+            {
+                total_match_count += 1;
+                pattern_match_counts[m.pattern_index] += 1;
+                let mut output = Vec::new();
+                if dot {
+                    if !quiet {
+                        write_match_dot_graph(&mut output, &query, &m, i)?;
+                    }
+                } else if xml {
+                    if !quiet {
+                        write_match_xml(&mut output, &query, &m, &source_code)?;
+                    }
+                } else if !quiet && prints_matches {
+                    writeln!(&mut output, "  pattern: {}", m.pattern_index)?;
+                    if properties {
+                        write_pattern_properties(&mut output, &query, m.pattern_index)?;
+                    }
                 }
                 for capture in m.captures {
-                    let start = capture.node.start_position();
-                    let end = capture.node.end_position();
+                    let start = position_encoding.start_position(capture.node, &source_code);
+                    let end = position_encoding.end_position(capture.node, &source_code);
                     let capture_name = &query.capture_names()[capture.index as usize];
-                    if !quiet {
+                    let is_shown = !named_only || capture.node.is_named();
+                    if let Some(raw_capture_index) = raw_capture_index {
+                        if capture.index == raw_capture_index && !quiet && is_shown {
+                            output.write_all(&source_code[capture.node.byte_range()])?;
+                            writeln!(&mut output)?;
+                        }
+                    } else if let Some(group_capture_index) = group_capture_index {
+                        // `--named-only` only affects which captures are printed, so the
+                        // captures of anonymous nodes are still counted.
+                        if capture.index == group_capture_index {
+                            let text = capture.node.utf8_text(&source_code).unwrap_or("");
+                            *group_counts.entry(text.to_string()).or_default() += 1;
+                        }
+                    } else if !quiet && !dot && !xml && is_shown {
                         if end.row == start.row {
                             writeln!(
-                                &mut stdout,
+                                &mut output,
                                 "    capture: {} - {capture_name}, start: {start}, end: {end}, text: `{}`",
                                 capture.index,
                                 capture.node.utf8_text(&source_code).unwrap_or("")
                             )?;
                         } else {
                             writeln!(
-                                &mut stdout,
+                                &mut output,
                                 "    capture: {capture_name}, start: {start}, end: {end}",
                             )?;
                         }
@@ -102,21 +286,351 @@ pub fn query_files_at_paths(
                         end: capture.node.end_position(),
                     });
                 }
+                if context > 0 && !quiet && !dot && prints_matches {
+                    if let Some(row) = m.captures.iter().map(|c| c.node.start_position().row).min()
+                    {
+                        write_context_lines(&mut output, &source_code, row, context)?;
+                    }
+                }
+                if sort {
+                    let start_byte = m.captures.iter().map(|c| c.node.start_byte()).min();
+                    sorted_output.push((start_byte.unwrap_or_default(), output));
+                } else {
+                    stdout.write_all(&output)?;
+                }
             }
         }
-        if query_cursor.did_exceed_match_limit() {
-            writeln!(
-                &mut stdout,
-                "  WARNING: Query exceeded maximum number of in-progress captures!"
-            )?;
+        // Matches are found in the order in which they finish, so with `--sort`, they
+        // are buffered and then printed in the order in which they start.
+        sorted_output.sort_by_key(|(start_byte, _)| *start_byte);
+        for (_, output) in sorted_output {
+            stdout.write_all(&output)?;
+        }
+        if dot {
+            writeln!(&mut stdout, "}}")?;
+        } else if xml {
+            writeln!(&mut stdout, "</file>")?;
+        }
+        if query_cursor.did_exceed_match_limit() && !silent {
+            // Anything after the graph or the XML element would make the output invalid.
+            if dot || xml {
+                eprintln!("WARNING: Query exceeded maximum number of in-progress captures!");
+            } else {
+                writeln!(
+                    &mut stdout,
+                    "  WARNING: Query exceeded maximum number of in-progress captures!"
+                )?;
+            }
+        }
+        if opts.time_json {
+            util::print_time_json(Path::new(&path), "query", start.elapsed());
         }
         if should_test {
-            query_testing::assert_expected_captures(&results, path, &mut parser, language)?;
+            let result =
+                query_testing::assert_expected_captures(&results, path, &mut parser, language);
+            if silent {
+                result.map_err(|_| anyhow!(""))?;
+            } else {
+                result?;
+            }
         }
         if print_time {
             writeln!(&mut stdout, "{:?}", start.elapsed())?;
         }
     }
 
+    if any {
+        return Err(anyhow!(""));
+    }
+
+    if group_capture_index.is_some() && !quiet {
+        let mut group_counts = group_counts.into_iter().collect::<Vec<_>>();
+        group_counts.sort_unstable_by(|(a_text, a_count), (b_text, b_count)| {
+            b_count.cmp(a_count).then_with(|| a_text.cmp(b_text))
+        });
+        let width = group_counts
+            .first()
+            .map_or(0, |(_, count)| count.to_string().len());
+        for (text, count) in group_counts {
+            writeln!(&mut stdout, "{count:>width$}  {}", text.escape_debug())?;
+        }
+    }
+
+    if report_unmatched {
+        write_unmatched_patterns(&mut stdout, &query, &query_source, &pattern_match_counts)?;
+    }
+
     Ok(())
 }
+
+fn write_unmatched_patterns(
+    stdout: &mut impl Write,
+    query: &Query,
+    query_source: &str,
+    pattern_match_counts: &[usize],
+) -> Result<()> {
+    let unmatched_patterns = pattern_match_counts
+        .iter()
+        .enumerate()
+        .filter(|(_, count)| **count == 0)
+        .map(|(pattern_index, _)| pattern_index)
+        .collect::<Vec<_>>();
+    if unmatched_patterns.is_empty() {
+        writeln!(stdout, "All patterns matched")?;
+        return Ok(());
+    }
+
+    writeln!(
+        stdout,
+        "{} of {} patterns never matched:",
+        unmatched_patterns.len(),
+        query.pattern_count()
+    )?;
+    for pattern_index in unmatched_patterns {
+        // A pattern's text runs until the start of the next one, minus any comments
+        // and blank lines that precede the next pattern.
+        let start = query.start_byte_for_pattern(pattern_index);
+        let end = if pattern_index + 1 < query.pattern_count() {
+            query.start_byte_for_pattern(pattern_index + 1)
+        } else {
+            query_source.len()
+        };
+        let mut lines = query_source[start..end].lines().collect::<Vec<_>>();
+        while lines
+            .last()
+            .is_some_and(|line| line.trim().is_empty() || line.trim_start().starts_with(';'))
+        {
+            lines.pop();
+        }
+        writeln!(stdout, "  pattern: {pattern_index}")?;
+        for line in lines {
+            writeln!(stdout, "    {line}")?;
+        }
+    }
+    Ok(())
+}
+
+/// Compile the query at the given path without running it, and report where
+/// compilation failed, if it did.
+pub fn validate_query_at_path(language: &Language, query_path: &Path, quiet: bool) -> Result<()> {
+    let query_source = read_query(query_path)?;
+    match Query::new(language, &query_source) {
+        Ok(query) => {
+            if !quiet {
+                println!(
+                    "{}: valid ({} patterns, {} captures)",
+                    query_path.display(),
+                    query.pattern_count(),
+                    query.capture_names().len()
+                );
+            }
+            Ok(())
+        }
+        Err(error) => Err(anyhow!(
+            "{}:{}:{}: {:?} error at byte offset {}: {}",
+            query_path.display(),
+            error.row + 1,
+            error.column + 1,
+            error.kind,
+            error.offset,
+            error.message
+        )),
+    }
+}
+
+/// Read the query at the given path, where `-` stands for the standard input.
+fn read_query(query_path: &Path) -> Result<String> {
+    if query_path == Path::new("-") {
+        let mut query_source = String::new();
+        io::stdin()
+            .read_to_string(&mut query_source)
+            .with_context(|| "Error reading query from stdin")?;
+        Ok(query_source)
+    } else {
+        fs::read_to_string(query_path)
+            .with_context(|| format!("Error reading query file {query_path:?}"))
+    }
+}
+
+/// Write the source lines within `context` lines of the given row, marking the row itself.
+fn write_context_lines(
+    stdout: &mut impl Write,
+    source_code: &[u8],
+    row: usize,
+    context: usize,
+) -> Result<()> {
+    let source_code = source_code.strip_suffix(b"\n").unwrap_or(source_code);
+    let lines = source_code.split(|b| *b == b'\n').collect::<Vec<_>>();
+    let first_row = row.saturating_sub(context);
+    let last_row = (row + context).min(lines.len() - 1);
+    let width = (last_row + 1).to_string().len();
+    for (i, line) in lines[first_row..=last_row].iter().enumerate() {
+        let line_row = first_row + i;
+        let marker = if line_row == row { '>' } else { ' ' };
+        let line = String::from_utf8_lossy(line.strip_suffix(b"\r").unwrap_or(line));
+        writeln!(
+            stdout,
+            "    {marker} {:>width$} |{}{line}",
+            line_row + 1,
+            if line.is_empty() { "" } else { " " },
+        )?;
+    }
+    Ok(())
+}
+
+/// Print the `#set!` properties, the `#is?` and `#is-not?` assertions, and the other
+/// general predicates of a pattern, in the syntax that they were written in.
+fn write_pattern_properties(
+    stdout: &mut impl Write,
+    query: &Query,
+    pattern_index: usize,
+) -> Result<()> {
+    let capture_names = query.capture_names();
+    let format_property = |property: &QueryProperty| {
+        let mut result = String::new();
+        if let Some(capture_id) = property.capture_id {
+            result += &format!(" @{}", capture_names[capture_id]);
+        }
+        result += &format!(" {}", property.key);
+        if let Some(value) = &property.value {
+            result += &format!(" \"{}\"", value.escape_debug());
+        }
+        result
+    };
+
+    for property in query.property_settings(pattern_index) {
+        writeln!(stdout, "    (#set!{})", format_property(property))?;
+    }
+    for (property, is_positive) in query.property_predicates(pattern_index) {
+        let operator = if *is_positive { "is?" } else { "is-not?" };
+        writeln!(stdout, "    (#{operator}{})", format_property(property))?;
+    }
+    for predicate in query.general_predicates(pattern_index) {
+        write!(stdout, "    (#{}", predicate.operator)?;
+        for arg in predicate.args.iter() {
+            match arg {
+                QueryPredicateArg::Capture(index) => {
+                    write!(stdout, " @{}", capture_names[*index as usize])?;
+                }
+                QueryPredicateArg::String(value) => {
+                    write!(stdout, " \"{}\"", value.escape_debug())?;
+                }
+            }
+        }
+        writeln!(stdout, ")")?;
+    }
+    Ok(())
+}
+
+/// Write a single match as a `match` element, containing the subtrees of its outermost
+/// captured nodes as XML, like `parse --xml`. Each captured node is labeled with its
+/// capture names. Like the other nodes in the XML output, anonymous nodes don't have
+/// their own elements, so their captures are left out.
+fn write_match_xml(
+    stdout: &mut impl Write,
+    query: &Query,
+    m: &QueryMatch,
+    source_code: &[u8],
+) -> Result<()> {
+    writeln!(stdout, "  <match pattern=\"{}\">", m.pattern_index)?;
+    let captured_ids = m
+        .captures
+        .iter()
+        .map(|capture| capture.node.id())
+        .collect::<HashSet<_>>();
+    let mut written_ids = HashSet::new();
+    for capture in m.captures {
+        let node = capture.node;
+        if !node.is_named() || !written_ids.insert(node.id()) {
+            continue;
+        }
+        let mut ancestor = node.parent();
+        while let Some(node) = ancestor {
+            if captured_ids.contains(&node.id()) {
+                break;
+            }
+            ancestor = node.parent();
+        }
+        if ancestor.is_some() {
+            continue;
+        }
+
+        parse::write_node_xml(stdout, node, source_code, 2, false, |node| {
+            let capture_names = m
+                .captures
+                .iter()
+                .filter(|capture| capture.node == node)
+                .map(|capture| query.capture_names()[capture.index as usize])
+                .collect::<Vec<_>>();
+            if capture_names.is_empty() {
+                Vec::new()
+            } else {
+                vec![("capture", capture_names.join(" "))]
+            }
+        })?;
+    }
+    writeln!(stdout, "  </match>")?;
+    Ok(())
+}
+
+/// Write the subtree spanned by a single match as a DOT cluster, rooted at the
+/// smallest node that contains all of the match's captures. Captured nodes are
+/// filled and labeled with their capture names.
+fn write_match_dot_graph(
+    stdout: &mut impl Write,
+    query: &Query,
+    m: &QueryMatch,
+    match_index: usize,
+) -> Result<()> {
+    let Some(root) = m
+        .captures
+        .iter()
+        .map(|capture| capture.node)
+        .reduce(common_ancestor)
+    else {
+        return Ok(());
+    };
+
+    writeln!(stdout, "  subgraph cluster_{match_index} {{")?;
+    writeln!(stdout, "    label=\"pattern: {}\";", m.pattern_index)?;
+    parse::write_node_dot_graph(stdout, root, &format!("m{match_index}_n"), 2, |node| {
+        let capture_names = m
+            .captures
+            .iter()
+            .filter(|capture| capture.node == node)
+            .map(|capture| format!("@{}", query.capture_names()[capture.index as usize]))
+            .collect::<Vec<_>>();
+        if capture_names.is_empty() {
+            return Vec::new();
+        }
+        let kind = if node.is_named() {
+            node.kind().to_string()
+        } else {
+            format!("\"{}\"", node.kind())
+        };
+        vec![
+            ("label", format!("{kind}\n{}", capture_names.join(" "))),
+            ("style", "filled".to_string()),
+            ("fillcolor", "yellow".to_string()),
+        ]
+    })?;
+    writeln!(stdout, "  }}")?;
+    Ok(())
+}
+
+fn common_ancestor<'tree>(a: Node<'tree>, b: Node<'tree>) -> Node<'tree> {
+    let mut ancestor_ids = HashSet::new();
+    let mut node = Some(a);
+    while let Some(n) = node {
+        ancestor_ids.insert(n.id());
+        node = n.parent();
+    }
+    let mut node = b;
+    while !ancestor_ids.contains(&node.id()) {
+        match node.parent() {
+            Some(parent) => node = parent,
+            None => break,
+        }
+    }
+    node
+}
@@ -1,21 +1,48 @@
 use super::util;
 use anyhow::{anyhow, Result};
 use std::io::{self, Write};
+use std::ops::Range;
 use std::path::Path;
+use std::str::FromStr;
 use std::time::Instant;
 use std::{fs, str};
+use tree_sitter::Tree;
 use tree_sitter_loader::Loader;
 use tree_sitter_tags::TagsContext;
 
-pub fn generate_tags(
-    loader: &Loader,
-    scope: Option<&str>,
-    paths: &[String],
-    quiet: bool,
-    time: bool,
-) -> Result<()> {
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum TagsSort {
+    Name,
+    Kind,
+    Position,
+}
+
+impl FromStr for TagsSort {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "name" => Ok(Self::Name),
+            "kind" => Ok(Self::Kind),
+            "position" => Ok(Self::Position),
+            _ => Err(anyhow!("Invalid tags sort order '{s}'")),
+        }
+    }
+}
+
+pub struct TagsOptions<'a> {
+    pub scope: Option<&'a str>,
+    pub quiet: bool,
+    pub time: bool,
+    pub time_json: bool,
+    pub sort: TagsSort,
+    pub query_paths: Option<&'a [String]>,
+    pub exclude_extras: bool,
+}
+
+pub fn generate_tags(loader: &Loader, paths: &[String], opts: &TagsOptions) -> Result<()> {
     let mut lang = None;
-    if let Some(scope) = scope {
+    if let Some(scope) = opts.scope {
         lang = loader.language_configuration_for_scope(scope)?;
         if lang.is_none() {
             return Err(anyhow!("Unknown scope '{scope}'"));
@@ -27,8 +54,15 @@ pub fn generate_tags(
     let stdout = io::stdout();
     let mut stdout = stdout.lock();
 
-    for path in paths {
-        let path = Path::new(&path);
+    // Unless the tags are listed in document order, list the files in order of
+    // their names too, so that the output is stable.
+    let mut paths = paths.iter().collect::<Vec<_>>();
+    if opts.sort != TagsSort::Position {
+        paths.sort();
+    }
+
+    for path in &paths {
+        let path = Path::new(path);
         let (language, language_config) = match lang.clone() {
             Some(v) => v,
             None => {
@@ -41,9 +75,11 @@ pub fn generate_tags(
             }
         };
 
-        if let Some(tags_config) = language_config.tags_config(language)? {
+        if let Some(tags_config) =
+            language_config.tags_config(language.clone(), opts.query_paths)?
+        {
             let indent = if paths.len() > 1 {
-                if !quiet {
+                if !opts.quiet {
                     writeln!(&mut stdout, "{}", path.to_string_lossy())?;
                 }
                 "\t"
@@ -53,12 +89,35 @@ pub fn generate_tags(
 
             let source = fs::read(path)?;
             let t0 = Instant::now();
-            for tag in context
+            let mut tags = context
                 .generate_tags(tags_config, &source, Some(&cancellation_flag))?
                 .0
-            {
-                let tag = tag?;
-                if !quiet {
+                .collect::<Result<Vec<_>, _>>()?;
+            // The tags don't say which nodes they came from, so the file is parsed again
+            // to find the ones that are inside of extras.
+            if opts.exclude_extras {
+                context.parser.set_language(&language)?;
+                if let Some(tree) = context.parser.parse(&source, None) {
+                    tags.retain(|tag| !is_inside_extra(&tree, tag.range.clone()));
+                }
+            }
+            match opts.sort {
+                TagsSort::Name => tags.sort_by(|a, b| {
+                    source[a.name_range.clone()].cmp(&source[b.name_range.clone()])
+                }),
+                TagsSort::Kind => tags.sort_by(|a, b| {
+                    tags_config
+                        .syntax_type_name(a.syntax_type_id)
+                        .cmp(tags_config.syntax_type_name(b.syntax_type_id))
+                        .then_with(|| {
+                            source[a.name_range.clone()].cmp(&source[b.name_range.clone()])
+                        })
+                }),
+                TagsSort::Position => {}
+            }
+
+            for tag in tags {
+                if !opts.quiet {
                     write!(
                         &mut stdout,
                         "{indent}{:<10}\t | {:<8}\t{} {} - {} `{}`",
@@ -80,9 +139,12 @@ pub fn generate_tags(
                 }
             }
 
-            if time {
+            if opts.time {
                 writeln!(&mut stdout, "{indent}time: {}ms", t0.elapsed().as_millis(),)?;
             }
+            if opts.time_json {
+                util::print_time_json(path, "tags", t0.elapsed());
+            }
         } else {
             eprintln!("No tags config found for path {path:?}");
         }
@@ -90,3 +152,16 @@ pub fn generate_tags(
 
     Ok(())
 }
+
+fn is_inside_extra(tree: &Tree, range: Range<usize>) -> bool {
+    let mut node = tree
+        .root_node()
+        .descendant_for_byte_range(range.start, range.end);
+    while let Some(n) = node {
+        if n.is_extra() {
+            return true;
+        }
+        node = n.parent();
+    }
+    false
+}
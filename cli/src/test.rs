@@ -1,17 +1,21 @@
+use super::parse::write_error_diagnostics;
 use super::util;
 use ansi_term::Colour;
 use anyhow::{anyhow, Context, Result};
 use difference::{Changeset, Difference};
 use lazy_static::lazy_static;
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
 use regex::bytes::{Regex as ByteRegex, RegexBuilder as ByteRegexBuilder};
 use regex::Regex;
+use std::collections::BTreeSet;
 use std::ffi::OsStr;
 use std::fmt::Write as FmtWrite;
-use std::fs;
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
-use std::str;
-use tree_sitter::{Language, LogType, Parser, Query};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::{env, fs, str};
+use tree_sitter::{Language, LogType, Parser, Query, Tree};
 use walkdir::WalkDir;
 
 lazy_static! {
@@ -59,19 +63,44 @@ impl Default for TestEntry {
 pub struct TestOptions<'a> {
     pub path: PathBuf,
     pub debug: bool,
-    pub debug_graph: bool,
+    pub debug_graph: Option<&'a str>,
     pub filter: Option<&'a str>,
     pub include: Option<Regex>,
     pub exclude: Option<Regex>,
     pub update: bool,
+    pub dry_run: bool,
+    pub coverage: bool,
+    pub normalize_whitespace: bool,
+    pub parse_only: bool,
+    pub failures_only: bool,
+    pub shuffle_seed: Option<u64>,
+    pub cancellation_flag: Option<&'a AtomicUsize>,
+}
+
+/// What `run_tests` has collected so far, while it walks the tree of tests.
+#[derive(Default)]
+struct TestResults {
+    /// The name, actual and expected tree of each failed test. With `--parse-only`,
+    /// the error diagnostics take the place of the actual tree.
+    failures: Vec<(String, String, String)>,
+    corrected_entries: Vec<(String, String, String, usize, usize)>,
+    produced_kinds: BTreeSet<&'static str>,
+    /// The headers of the enclosing groups that haven't been printed yet, because
+    /// with `--failures-only` a group is only printed once one of its tests fails.
+    group_headers: Vec<Option<String>>,
 }
 
 pub fn run_tests_at_path(parser: &mut Parser, opts: &mut TestOptions) -> Result<()> {
-    let test_entry = parse_tests(&opts.path)?;
+    let mut test_entry = parse_tests(&opts.path)?;
+    if let Some(seed) = opts.shuffle_seed {
+        println!("Running the tests in a random order, with seed {seed}");
+        shuffle_tests(&mut test_entry, &mut StdRng::seed_from_u64(seed));
+    }
     let mut _log_session = None;
 
-    if opts.debug_graph {
-        _log_session = Some(util::log_graphs(parser, "log.html")?);
+    if let Some(debug_graph) = opts.debug_graph {
+        let graph_path = util::debug_graph_path(debug_graph, &opts.path);
+        _log_session = Some(util::log_graphs(parser, &graph_path)?);
     } else if opts.debug {
         parser.set_logger(Some(Box::new(|log_type, message| {
             if log_type == LogType::Lex {
@@ -81,25 +110,62 @@ pub fn run_tests_at_path(parser: &mut Parser, opts: &mut TestOptions) -> Result<
         })));
     }
 
-    let mut failures = Vec::new();
-    let mut corrected_entries = Vec::new();
-    run_tests(
-        parser,
-        test_entry,
-        opts,
-        0,
-        &mut failures,
-        &mut corrected_entries,
-    )?;
+    let mut results = TestResults::default();
+    run_tests(parser, test_entry, opts, 0, &mut results)?;
+    let TestResults {
+        failures,
+        produced_kinds,
+        ..
+    } = results;
 
     parser.stop_printing_dot_graphs();
 
+    let interrupted = is_cancelled(opts);
+    if interrupted {
+        println!("\nInterrupted. The remaining tests were not run.");
+    }
+
+    if opts.coverage {
+        if let Some(language) = parser.language() {
+            print_coverage(&language, &produced_kinds);
+        }
+    }
+
     if failures.is_empty() {
-        Ok(())
+        if interrupted {
+            Err(anyhow!(""))
+        } else {
+            Ok(())
+        }
     } else {
         println!();
 
-        if opts.update {
+        if opts.update && opts.dry_run {
+            // Nothing was written, so show how each test would have been changed.
+            if failures.len() == 1 {
+                println!("1 update would be made:");
+            } else {
+                println!("{} updates would be made:", failures.len());
+            }
+
+            println!(
+                "\n{} / {} / {}",
+                util::paint(Colour::White, "unchanged"),
+                util::paint(Colour::Green, "new"),
+                util::paint(Colour::Red, "old")
+            );
+            for (i, (name, actual, expected)) in failures.iter().enumerate() {
+                println!("\n  {}. {name}:", i + 1);
+                let actual = format_sexp_indented(actual, 2);
+                let expected = format_sexp_indented(expected, 2);
+                print_diff(&expected, &actual);
+            }
+            if interrupted {
+                Err(anyhow!(""))
+            } else {
+                Ok(())
+            }
+        } else if opts.update {
             if failures.len() == 1 {
                 println!("1 update:\n");
             } else {
@@ -109,7 +175,11 @@ pub fn run_tests_at_path(parser: &mut Parser, opts: &mut TestOptions) -> Result<
             for (i, (name, ..)) in failures.iter().enumerate() {
                 println!("  {}. {name}", i + 1);
             }
-            Ok(())
+            if interrupted {
+                Err(anyhow!(""))
+            } else {
+                Ok(())
+            }
         } else {
             if failures.len() == 1 {
                 println!("1 failure:");
@@ -117,18 +187,59 @@ pub fn run_tests_at_path(parser: &mut Parser, opts: &mut TestOptions) -> Result<
                 println!("{} failures:", failures.len());
             }
 
-            print_diff_key();
-            for (i, (name, actual, expected)) in failures.iter().enumerate() {
-                println!("\n  {}. {name}:", i + 1);
-                let actual = format_sexp_indented(actual, 2);
-                let expected = format_sexp_indented(expected, 2);
-                print_diff(&actual, &expected);
+            if opts.parse_only {
+                for (i, (name, diagnostics, _)) in failures.iter().enumerate() {
+                    println!("\n  {}. {name}:", i + 1);
+                    for line in diagnostics.lines() {
+                        println!("    {line}");
+                    }
+                }
+            } else {
+                print_diff_key();
+                for (i, (name, actual, expected)) in failures.iter().enumerate() {
+                    println!("\n  {}. {name}:", i + 1);
+                    let actual = format_sexp_indented(actual, 2);
+                    let expected = format_sexp_indented(expected, 2);
+                    print_diff(&actual, &expected);
+                }
             }
             Err(anyhow!(""))
         }
     }
 }
 
+/// Run the tests by invoking this executable again without `--watch`, and run them
+/// again each time the grammar in `grammar_dir`, its corpus, or its queries change.
+pub fn watch_tests_at_path(grammar_dir: &Path) -> Result<()> {
+    let watched_paths = ["grammar.js", "src", "test", "corpus", "queries"]
+        .iter()
+        .map(|name| grammar_dir.join(name))
+        .collect::<Vec<_>>();
+    let executable = env::current_exe()?;
+    util::watch_paths(&watched_paths, true, None, || {
+        if needs_regenerate(grammar_dir) {
+            Command::new(&executable)
+                .arg("generate")
+                .current_dir(grammar_dir)
+                .status()
+                .with_context(|| "Failed to regenerate the parser")?;
+        }
+        Ok(())
+    })
+}
+
+fn needs_regenerate(grammar_dir: &Path) -> bool {
+    let modified_time = |path: PathBuf| fs::metadata(path).and_then(|m| m.modified()).ok();
+    match (
+        modified_time(grammar_dir.join("grammar.js")),
+        modified_time(grammar_dir.join("src").join("parser.c")),
+    ) {
+        (Some(grammar_time), Some(parser_time)) => grammar_time > parser_time,
+        (Some(_), None) => true,
+        _ => false,
+    }
+}
+
 pub fn check_queries_at_path(language: &Language, path: &Path) -> Result<()> {
     if path.exists() {
         for entry in WalkDir::new(path)
@@ -153,9 +264,9 @@ pub fn check_queries_at_path(language: &Language, path: &Path) -> Result<()> {
 pub fn print_diff_key() {
     println!(
         "\n{} / {} / {}",
-        Colour::White.paint("correct"),
-        Colour::Green.paint("expected"),
-        Colour::Red.paint("unexpected")
+        util::paint(Colour::White, "correct"),
+        util::paint(Colour::Green, "expected"),
+        util::paint(Colour::Red, "unexpected")
     );
 }
 
@@ -167,23 +278,46 @@ pub fn print_diff(actual: &str, expected: &str) {
                 print!("{part}{}", changeset.split);
             }
             Difference::Add(part) => {
-                print!("{}{}", Colour::Green.paint(part), changeset.split);
+                print!("{}{}", util::paint(Colour::Green, part), changeset.split);
             }
             Difference::Rem(part) => {
-                print!("{}{}", Colour::Red.paint(part), changeset.split);
+                print!("{}{}", util::paint(Colour::Red, part), changeset.split);
             }
         }
     }
     println!();
 }
 
+/// Print the result of a single test. With `--failures-only`, passing tests aren't
+/// printed, and the names of the groups are only printed once they contain a failure.
+fn print_test_result(
+    opts: &TestOptions,
+    group_headers: &mut [Option<String>],
+    indent_level: i32,
+    passed: bool,
+    color: Colour,
+    name: &str,
+) {
+    if passed && opts.failures_only {
+        return;
+    }
+    for header in group_headers.iter_mut().filter_map(Option::take) {
+        println!("{header}");
+    }
+    println!(
+        "{}{} {}",
+        "  ".repeat(indent_level as usize),
+        if color == Colour::Red { "✗" } else { "✓" },
+        util::paint(color, name)
+    );
+}
+
 fn run_tests(
     parser: &mut Parser,
     test_entry: TestEntry,
     opts: &mut TestOptions,
     mut indent_level: i32,
-    failures: &mut Vec<(String, String, String)>,
-    corrected_entries: &mut Vec<(String, String, String, usize, usize)>,
+    results: &mut TestResults,
 ) -> Result<()> {
     match test_entry {
         TestEntry::Example {
@@ -194,18 +328,74 @@ fn run_tests(
             divider_delim_len,
             has_fields,
         } => {
-            let tree = parser.parse(&input, None).unwrap();
+            // After an interrupt, finish the current test but don't start any more.
+            if is_cancelled(opts) {
+                return Ok(());
+            }
+            let tree = parser.parse(&input, None).ok_or_else(|| {
+                if util::ran_out_of_wasm_fuel(parser) {
+                    anyhow!("Parsing of test {name:?} ran out of wasm fuel")
+                } else {
+                    anyhow!("Parsing of test {name:?} was cancelled")
+                }
+            })?;
+            if opts.coverage {
+                record_node_kinds(&tree, &mut results.produced_kinds);
+            }
             let mut actual = tree.root_node().to_sexp();
             if !has_fields {
                 actual = strip_sexp_fields(&actual);
             }
-            print!("{}", "  ".repeat(indent_level as usize));
-            if actual == output {
-                println!("✓ {}", Colour::Green.paint(&name));
+            // With `--parse-only`, the expected tree is ignored, and a test only fails if
+            // its input has parse errors that the expected tree doesn't anticipate.
+            if opts.parse_only {
+                let expects_errors = output.contains("(ERROR") || output.contains("(MISSING");
+                if tree.root_node().has_error() && !expects_errors {
+                    print_test_result(
+                        opts,
+                        &mut results.group_headers,
+                        indent_level,
+                        false,
+                        Colour::Red,
+                        &name,
+                    );
+                    // The tree isn't compared to anything, so instead of a diff, the
+                    // summary shows where the errors are.
+                    let mut diagnostics = Vec::new();
+                    write_error_diagnostics(&mut diagnostics, &tree, &input, Path::new(&name))?;
+                    let diagnostics = String::from_utf8_lossy(&diagnostics).into_owned();
+                    results.failures.push((name, diagnostics, String::new()));
+                } else {
+                    print_test_result(
+                        opts,
+                        &mut results.group_headers,
+                        indent_level,
+                        true,
+                        Colour::Green,
+                        &name,
+                    );
+                }
+                return Ok(());
+            }
+
+            let passed = if opts.normalize_whitespace {
+                normalize_sexp_whitespace(&actual) == normalize_sexp_whitespace(&output)
+            } else {
+                actual == output
+            };
+            if passed {
+                print_test_result(
+                    opts,
+                    &mut results.group_headers,
+                    indent_level,
+                    true,
+                    Colour::Green,
+                    &name,
+                );
                 if opts.update {
                     let input = String::from_utf8(input).unwrap();
                     let output = format_sexp(&output);
-                    corrected_entries.push((
+                    results.corrected_entries.push((
                         name,
                         input,
                         output,
@@ -217,18 +407,32 @@ fn run_tests(
                 if opts.update {
                     let input = String::from_utf8(input).unwrap();
                     let output = format_sexp(&actual);
-                    corrected_entries.push((
+                    results.corrected_entries.push((
                         name.clone(),
                         input,
                         output,
                         header_delim_len,
                         divider_delim_len,
                     ));
-                    println!("✓ {}", Colour::Blue.paint(&name));
+                    print_test_result(
+                        opts,
+                        &mut results.group_headers,
+                        indent_level,
+                        false,
+                        Colour::Blue,
+                        &name,
+                    );
                 } else {
-                    println!("✗ {}", Colour::Red.paint(&name));
+                    print_test_result(
+                        opts,
+                        &mut results.group_headers,
+                        indent_level,
+                        false,
+                        Colour::Red,
+                        &name,
+                    );
                 }
-                failures.push((name, actual, output));
+                results.failures.push((name, actual, output));
             }
         }
         TestEntry::Group {
@@ -262,35 +466,135 @@ fn run_tests(
             }
 
             if indent_level > 0 {
-                print!("{}", "  ".repeat(indent_level as usize));
-                println!("{name}:");
+                let header = format!("{}{name}:", "  ".repeat(indent_level as usize));
+                if opts.failures_only {
+                    results.group_headers.push(Some(header));
+                } else {
+                    println!("{header}");
+                }
             }
 
-            let failure_count = failures.len();
+            let failure_count = results.failures.len();
 
             indent_level += 1;
             for child in children {
-                run_tests(
-                    parser,
-                    child,
-                    opts,
-                    indent_level,
-                    failures,
-                    corrected_entries,
-                )?;
+                run_tests(parser, child, opts, indent_level, results)?;
+            }
+            if indent_level > 1 && opts.failures_only {
+                results.group_headers.pop();
             }
 
             if let Some(file_path) = file_path {
-                if opts.update && failures.len() - failure_count > 0 {
-                    write_tests(&file_path, corrected_entries)?;
+                // Don't rewrite a file whose tests were only partly run, because
+                // the tests that didn't run would be dropped from it.
+                if opts.update
+                    && !opts.dry_run
+                    && results.failures.len() - failure_count > 0
+                    && !is_cancelled(opts)
+                {
+                    write_tests(&file_path, &results.corrected_entries)?;
                 }
-                corrected_entries.clear();
+                results.corrected_entries.clear();
             }
         }
     }
     Ok(())
 }
 
+/// Shuffle the files and the tests within each file, so that tests which only pass
+/// after some other test has run are noticed.
+fn shuffle_tests(test_entry: &mut TestEntry, rng: &mut StdRng) {
+    if let TestEntry::Group { children, .. } = test_entry {
+        children.shuffle(rng);
+        for child in children {
+            shuffle_tests(child, rng);
+        }
+    }
+}
+
+fn is_cancelled(opts: &TestOptions) -> bool {
+    opts.cancellation_flag
+        .is_some_and(|flag| flag.load(Ordering::Relaxed) != 0)
+}
+
+fn record_node_kinds(tree: &Tree, produced_kinds: &mut BTreeSet<&'static str>) {
+    let mut cursor = tree.walk();
+    loop {
+        produced_kinds.insert(cursor.node().kind());
+        if cursor.goto_first_child() || cursor.goto_next_sibling() {
+            continue;
+        }
+        loop {
+            if !cursor.goto_parent() {
+                return;
+            }
+            if cursor.goto_next_sibling() {
+                break;
+            }
+        }
+    }
+}
+
+fn print_coverage(language: &Language, produced_kinds: &BTreeSet<&'static str>) {
+    let unused_kinds = (0..language.node_kind_count() as u16)
+        .filter(|id| language.node_kind_is_named(*id) && language.node_kind_is_visible(*id))
+        .filter_map(|id| language.node_kind_for_id(id))
+        .filter(|kind| !produced_kinds.contains(kind))
+        .collect::<BTreeSet<_>>();
+
+    println!();
+    if unused_kinds.is_empty() {
+        println!("All named node types were produced by the corpus");
+    } else {
+        println!(
+            "{} named node types were never produced by the corpus:",
+            unused_kinds.len()
+        );
+        for kind in unused_kinds {
+            println!("  {kind}");
+        }
+    }
+}
+
+/// Canonicalize the whitespace in an S-expression, so that trees that only differ in
+/// their formatting are equal. Tokens are separated by single spaces, except after an
+/// opening parenthesis and before a closing parenthesis or a field name's colon.
+fn normalize_sexp_whitespace(sexp: &str) -> String {
+    let mut result = String::with_capacity(sexp.len());
+    let mut chars = sexp.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+        if !result.is_empty() && !result.ends_with('(') && c != ')' && c != ':' {
+            result.push(' ');
+        }
+        result.push(c);
+        match c {
+            '(' | ')' | ':' => {}
+            // Quoted tokens, like `(UNEXPECTED ' ')`, are copied verbatim. Their first
+            // character can be the quote itself.
+            '\'' | '"' => {
+                result.extend(chars.next());
+                for next in chars.by_ref() {
+                    result.push(next);
+                    if next == c {
+                        break;
+                    }
+                }
+            }
+            _ => {
+                while let Some(next) = chars.next_if(|next| {
+                    !next.is_whitespace() && !matches!(next, '(' | ')' | ':' | '\'' | '"')
+                }) {
+                    result.push(next);
+                }
+            }
+        }
+    }
+    result
+}
+
 fn format_sexp(sexp: &str) -> String {
     format_sexp_indented(sexp, 0)
 }
@@ -683,6 +987,22 @@ abc
         );
     }
 
+    #[test]
+    fn test_normalize_sexp_whitespace() {
+        assert_eq!(
+            normalize_sexp_whitespace("(a\n  b: (c)\n  (d)\n  e : ( f\n    (MISSING \"(\") ) )"),
+            "(a b: (c) (d) e: (f (MISSING \"(\")))"
+        );
+        assert_eq!(
+            normalize_sexp_whitespace("(program (ERROR (UNEXPECTED ' '))(identifier))"),
+            "(program (ERROR (UNEXPECTED ' ')) (identifier))"
+        );
+        assert_ne!(
+            normalize_sexp_whitespace("(a (b) (c))"),
+            normalize_sexp_whitespace("(a (b (c)))")
+        );
+    }
+
     #[test]
     fn test_format_sexp() {
         assert_eq!(format_sexp(""), "");
@@ -971,4 +1291,40 @@ code with ----
             }
         );
     }
+
+    #[test]
+    fn test_shuffle_tests_with_seed() {
+        let corpus = || {
+            parse_test_content(
+                "the-filename".to_string(),
+                &(0..10)
+                    .map(|i| format!("=====\ntest {i}\n=====\nx\n---\n(a)\n"))
+                    .collect::<String>(),
+                None,
+            )
+        };
+        let names = |entry: &TestEntry| match entry {
+            TestEntry::Group { children, .. } => children
+                .iter()
+                .map(|child| match child {
+                    TestEntry::Example { name, .. } => name.clone(),
+                    TestEntry::Group { .. } => unreachable!(),
+                })
+                .collect::<Vec<_>>(),
+            TestEntry::Example { .. } => unreachable!(),
+        };
+
+        let mut first = corpus();
+        shuffle_tests(&mut first, &mut StdRng::seed_from_u64(7));
+        let mut second = corpus();
+        shuffle_tests(&mut second, &mut StdRng::seed_from_u64(7));
+        assert_eq!(first, second);
+
+        let mut shuffled_names = names(&first);
+        assert_ne!(shuffled_names, names(&corpus()));
+        shuffled_names.sort();
+        let mut original_names = names(&corpus());
+        original_names.sort();
+        assert_eq!(shuffled_names, original_names);
+    }
 }
@@ -1,4 +1,5 @@
 use crate::query_testing::{parse_position_comments, Assertion};
+use crate::util;
 use ansi_term::Colour;
 use anyhow::{anyhow, Result};
 use std::fs;
@@ -95,13 +96,13 @@ fn test_highlights_indented(
                 Ok(assertion_count) => {
                     println!(
                         "✓ {} ({assertion_count} assertions)",
-                        Colour::Green.paint(test_file_name.to_string_lossy().as_ref()),
+                        util::paint(Colour::Green, test_file_name.to_string_lossy().as_ref()),
                     );
                 }
                 Err(e) => {
                     println!(
                         "✗ {}",
-                        Colour::Red.paint(test_file_name.to_string_lossy().as_ref())
+                        util::paint(Colour::Red, test_file_name.to_string_lossy().as_ref())
                     );
                     println!(
                         "{indent:indent_level$}  {e}",
@@ -1,4 +1,5 @@
 use crate::query_testing::{parse_position_comments, Assertion};
+use crate::util;
 use ansi_term::Colour;
 use anyhow::{anyhow, Result};
 use std::fs;
@@ -50,7 +51,7 @@ pub fn test_tags(loader: &Loader, tags_context: &mut TagsContext, directory: &Pa
             .language_configuration_for_file_name(&test_file_path)?
             .ok_or_else(|| anyhow!("No language found for path {:?}", test_file_path))?;
         let tags_config = language_config
-            .tags_config(language)?
+            .tags_config(language, None)?
             .ok_or_else(|| anyhow!("No tags config found for {:?}", test_file_path))?;
         match test_tag(
             tags_context,
@@ -60,13 +61,13 @@ pub fn test_tags(loader: &Loader, tags_context: &mut TagsContext, directory: &Pa
             Ok(assertion_count) => {
                 println!(
                     "  ✓ {} ({assertion_count} assertions)",
-                    Colour::Green.paint(test_file_name.to_string_lossy().as_ref()),
+                    util::paint(Colour::Green, test_file_name.to_string_lossy().as_ref()),
                 );
             }
             Err(e) => {
                 println!(
                     "  ✗ {}",
-                    Colour::Red.paint(test_file_name.to_string_lossy().as_ref())
+                    util::paint(Colour::Red, test_file_name.to_string_lossy().as_ref())
                 );
                 println!("    {e}");
                 failed = true;
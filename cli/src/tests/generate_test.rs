@@ -0,0 +1,142 @@
+use crate::generate::generate_parser_in_directory;
+use std::fs;
+use std::path::Path;
+
+// A grammar with enough fields, aliases, supertypes and conflicting tokens that any
+// dependence on hash map ordering would show up in the generated files.
+const GRAMMAR: &str = r##"{
+  "name": "reproducible",
+  "word": "identifier",
+  "extras": [{"type": "PATTERN", "value": "\\s"}],
+  "supertypes": ["expression"],
+  "rules": {
+    "program": {
+      "type": "REPEAT",
+      "content": {"type": "SYMBOL", "name": "statement"}
+    },
+    "statement": {
+      "type": "SEQ",
+      "members": [
+        {
+          "type": "FIELD",
+          "name": "body",
+          "content": {"type": "SYMBOL", "name": "expression"}
+        },
+        {"type": "STRING", "value": ";"}
+      ]
+    },
+    "expression": {
+      "type": "CHOICE",
+      "members": [
+        {"type": "SYMBOL", "name": "identifier"},
+        {"type": "SYMBOL", "name": "number"},
+        {"type": "SYMBOL", "name": "call"},
+        {"type": "SYMBOL", "name": "binary"}
+      ]
+    },
+    "call": {
+      "type": "PREC",
+      "value": 3,
+      "content": {
+        "type": "SEQ",
+        "members": [
+          {
+            "type": "FIELD",
+            "name": "function",
+            "content": {
+              "type": "ALIAS",
+              "content": {"type": "SYMBOL", "name": "identifier"},
+              "named": true,
+              "value": "function_name"
+            }
+          },
+          {"type": "STRING", "value": "("},
+          {
+            "type": "FIELD",
+            "name": "arguments",
+            "content": {
+              "type": "REPEAT",
+              "content": {"type": "SYMBOL", "name": "expression"}
+            }
+          },
+          {"type": "STRING", "value": ")"}
+        ]
+      }
+    },
+    "binary": {
+      "type": "CHOICE",
+      "members": [
+        {
+          "type": "PREC_LEFT",
+          "value": 1,
+          "content": {
+            "type": "SEQ",
+            "members": [
+              {"type": "FIELD", "name": "left", "content": {"type": "SYMBOL", "name": "expression"}},
+              {"type": "FIELD", "name": "operator", "content": {"type": "STRING", "value": "+"}},
+              {"type": "FIELD", "name": "right", "content": {"type": "SYMBOL", "name": "expression"}}
+            ]
+          }
+        },
+        {
+          "type": "PREC_LEFT",
+          "value": 2,
+          "content": {
+            "type": "SEQ",
+            "members": [
+              {"type": "FIELD", "name": "left", "content": {"type": "SYMBOL", "name": "expression"}},
+              {"type": "FIELD", "name": "operator", "content": {"type": "STRING", "value": "*"}},
+              {"type": "FIELD", "name": "right", "content": {"type": "SYMBOL", "name": "expression"}}
+            ]
+          }
+        },
+        {
+          "type": "PREC_LEFT",
+          "value": 1,
+          "content": {
+            "type": "SEQ",
+            "members": [
+              {"type": "FIELD", "name": "left", "content": {"type": "SYMBOL", "name": "expression"}},
+              {"type": "FIELD", "name": "operator", "content": {"type": "STRING", "value": "plus"}},
+              {"type": "FIELD", "name": "right", "content": {"type": "SYMBOL", "name": "expression"}}
+            ]
+          }
+        }
+      ]
+    },
+    "identifier": {"type": "PATTERN", "value": "[a-z_]+"},
+    "number": {"type": "PATTERN", "value": "\\d+"}
+  }
+}"##;
+
+fn generate_in(dir: &Path) -> (Vec<u8>, Vec<u8>) {
+    let grammar_path = dir.join("grammar.json");
+    fs::write(&grammar_path, GRAMMAR).unwrap();
+    generate_parser_in_directory(
+        dir,
+        Some(grammar_path.to_str().unwrap()),
+        tree_sitter::LANGUAGE_VERSION,
+        false,
+        None,
+        None,
+        false,
+    )
+    .unwrap();
+    (
+        fs::read(dir.join("src/parser.c")).unwrap(),
+        fs::read(dir.join("src/node-types.json")).unwrap(),
+    )
+}
+
+#[test]
+fn test_generate_is_reproducible() {
+    let first_dir = tempfile::tempdir().unwrap();
+    let second_dir = tempfile::tempdir().unwrap();
+    let (first_parser, first_node_types) = generate_in(first_dir.path());
+    let (second_parser, second_node_types) = generate_in(second_dir.path());
+    assert!(first_parser == second_parser, "parser.c differs");
+    assert!(
+        first_node_types == second_node_types,
+        "node-types.json differs"
+    );
+}
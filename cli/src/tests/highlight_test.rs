@@ -210,6 +210,58 @@ fn test_highlighting_injected_javascript_in_html() {
     );
 }
 
+#[test]
+fn test_highlighting_with_languages() {
+    let source = "<script>const x = new Thing();</script>";
+
+    let mut highlighter = Highlighter::new();
+    let events = highlighter
+        .highlight_with_languages(
+            &HTML_HIGHLIGHT,
+            source.as_bytes(),
+            None,
+            &test_language_for_injection_string,
+        )
+        .unwrap();
+    let mut language_stack = Vec::new();
+    let mut tokens = Vec::new();
+    for event in events {
+        match event.unwrap() {
+            (HighlightEvent::HighlightStart(_), language_name) => {
+                language_stack.push(language_name);
+            }
+            (HighlightEvent::HighlightEnd, _) => {
+                language_stack.pop();
+            }
+            (HighlightEvent::Source { start, end }, _) => {
+                if let Some(language_name) = language_stack.last() {
+                    tokens.push((&source[start..end], *language_name));
+                }
+            }
+        }
+    }
+
+    assert_eq!(
+        tokens,
+        &[
+            ("<", "html"),
+            ("script", "html"),
+            (">", "html"),
+            ("const", "javascript"),
+            ("x", "javascript"),
+            ("=", "javascript"),
+            ("new", "javascript"),
+            ("Thing", "javascript"),
+            ("(", "javascript"),
+            (")", "javascript"),
+            (";", "javascript"),
+            ("</", "html"),
+            ("script", "html"),
+            (">", "html"),
+        ]
+    );
+}
+
 #[test]
 fn test_highlighting_multiline_nodes_to_html() {
     let source = [
@@ -1,10 +1,12 @@
 mod async_context_test;
 mod corpus_test;
+mod generate_test;
 mod github_issue_test;
 mod helpers;
 mod highlight_test;
 mod language_test;
 mod node_test;
+mod parse_output_test;
 mod parser_hang_test;
 mod parser_test;
 mod pathological_test;
@@ -0,0 +1,187 @@
+use super::helpers::fixtures::get_test_language;
+use crate::generate::generate_parser_for_grammar;
+use crate::parse::{write_saved_tree, write_saved_trees, write_tree_sexp, SexpOptions};
+use std::path::Path;
+use tree_sitter::{Parser, Tree};
+
+const NESTED_GRAMMAR: &str = r##"{
+  "name": "nested_items",
+
+  "extras": [
+    {"type": "PATTERN", "value": "\\s+"},
+    {"type": "SYMBOL", "name": "comment"}
+  ],
+
+  "rules": {
+    "document": {
+      "type": "REPEAT",
+      "content": {"type": "SYMBOL", "name": "item"}
+    },
+
+    "item": {
+      "type": "CHOICE",
+      "members": [
+        {
+          "type": "FIELD",
+          "name": "value",
+          "content": {"type": "SYMBOL", "name": "number"}
+        },
+        {
+          "type": "SEQ",
+          "members": [
+            {"type": "STRING", "value": "("},
+            {
+              "type": "FIELD",
+              "name": "inner",
+              "content": {"type": "SYMBOL", "name": "item"}
+            },
+            {"type": "STRING", "value": ")"}
+          ]
+        }
+      ]
+    },
+
+    "number": {"type": "PATTERN", "value": "\\d+"},
+
+    "comment": {"type": "PATTERN", "value": "#[^\\n]*"}
+  }
+}"##;
+
+fn parse_nested(source: &str) -> Tree {
+    let (parser_name, parser_code) = generate_parser_for_grammar(NESTED_GRAMMAR).unwrap();
+    let mut parser = Parser::new();
+    parser
+        .set_language(&get_test_language(&parser_name, &parser_code, None))
+        .unwrap();
+    parser.parse(source, None).unwrap()
+}
+
+fn sexp(tree: &Tree, opts: &SexpOptions) -> String {
+    let mut output = Vec::new();
+    write_tree_sexp(&mut output, tree, opts).unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+fn saved_sexp(trees: &[&Tree], opts: &SexpOptions) -> String {
+    let mut contents = Vec::new();
+    for (i, tree) in trees.iter().enumerate() {
+        write_saved_tree(&mut contents, Path::new(&format!("{i}.txt")), tree).unwrap();
+    }
+    let mut output = Vec::new();
+    write_saved_trees(&mut output, &contents, opts).unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn test_saved_tree_output_matches_parse_output() {
+    let tree = parse_nested("1 ((2 # two\n)) # three\n(3)");
+    for opts in [
+        SexpOptions::default(),
+        SexpOptions {
+            no_ranges: true,
+            ..Default::default()
+        },
+        SexpOptions {
+            compact: true,
+            ..Default::default()
+        },
+        SexpOptions {
+            drop_extras: true,
+            ..Default::default()
+        },
+        SexpOptions {
+            filter_node: Some("item"),
+            ..Default::default()
+        },
+    ] {
+        assert_eq!(saved_sexp(&[&tree], &opts), sexp(&tree, &opts));
+    }
+
+    assert_eq!(
+        sexp(
+            &tree,
+            &SexpOptions {
+                no_ranges: true,
+                ..Default::default()
+            }
+        ),
+        concat!(
+            "(document\n",
+            "  (item\n",
+            "    value: (number))\n",
+            "  (item\n",
+            "    inner: (item\n",
+            "      inner: (item\n",
+            "        value: (number))\n",
+            "      (comment)))\n",
+            "  (comment)\n",
+            "  (item\n",
+            "    inner: (item\n",
+            "      value: (number))))\n",
+        )
+    );
+}
+
+#[test]
+fn test_filtered_output_omits_the_field_names_of_the_subtree_roots() {
+    let tree = parse_nested("1 ((2))");
+    let opts = SexpOptions {
+        no_ranges: true,
+        filter_node: Some("item"),
+        ..Default::default()
+    };
+    assert_eq!(saved_sexp(&[&tree], &opts), sexp(&tree, &opts));
+    assert_eq!(
+        sexp(&tree, &opts),
+        concat!(
+            "(item\n",
+            "  value: (number))\n",
+            "(item\n",
+            "  inner: (item\n",
+            "    inner: (item\n",
+            "      value: (number))))\n",
+        )
+    );
+
+    let opts = SexpOptions {
+        no_ranges: true,
+        filter_node: Some("number"),
+        ..Default::default()
+    };
+    assert_eq!(saved_sexp(&[&tree], &opts), sexp(&tree, &opts));
+    assert_eq!(sexp(&tree, &opts), "(number)\n(number)\n");
+}
+
+#[test]
+fn test_saved_tree_output_with_several_trees() {
+    let tree1 = parse_nested("1");
+    let tree2 = parse_nested("(2)");
+    let opts = SexpOptions {
+        no_ranges: true,
+        compact: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        saved_sexp(&[&tree1, &tree2], &opts),
+        format!(
+            "; 0.txt\n{}; 1.txt\n{}",
+            sexp(&tree1, &opts),
+            sexp(&tree2, &opts)
+        )
+    );
+}
+
+#[test]
+fn test_saved_tree_output_for_deeply_nested_trees() {
+    let depth = 100_000;
+    let source = format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+    let tree = parse_nested(&source);
+    let opts = SexpOptions {
+        no_ranges: true,
+        compact: true,
+        ..Default::default()
+    };
+    let output = saved_sexp(&[&tree], &opts);
+    assert_eq!(output, sexp(&tree, &opts));
+    assert_eq!(output.matches("(item").count(), depth + 1);
+}
@@ -2,7 +2,8 @@ use crate::tests::helpers::{allocations, fixtures::WASM_DIR};
 use lazy_static::lazy_static;
 use std::fs;
 use tree_sitter::{
-    wasmtime::Engine, Parser, Query, QueryCursor, WasmError, WasmErrorKind, WasmStore,
+    wasmtime::{Config, Engine},
+    Parser, Query, QueryCursor, WasmError, WasmErrorKind, WasmStore,
 };
 
 lazy_static! {
@@ -158,3 +159,29 @@ fn test_load_wasm_errors() {
         );
     });
 }
+
+#[test]
+fn test_wasm_parse_running_out_of_fuel() {
+    let engine = Engine::new(Config::new().consume_fuel(true)).unwrap();
+    let mut store = WasmStore::new(engine).unwrap();
+    let wasm = fs::read(WASM_DIR.join("tree-sitter-rust.wasm")).unwrap();
+    let language = store.load_language("rust", &wasm).unwrap();
+    let source = "fn main() { let x = 1; }";
+
+    let mut parser = Parser::new();
+    store.set_fuel(1);
+    parser.set_wasm_store(store).unwrap();
+    parser.set_language(&language).unwrap();
+    assert!(parser.parse(source, None).is_none());
+
+    let mut store = parser.take_wasm_store().unwrap();
+    assert!(store.ran_out_of_fuel());
+
+    // Without a limit, the same parse succeeds.
+    store.set_fuel(0);
+    parser.set_wasm_store(store).unwrap();
+    parser.reset();
+    let tree = parser.parse(source, None).unwrap();
+    assert_eq!(tree.root_node().kind(), "source_file");
+    assert!(!parser.take_wasm_store().unwrap().ran_out_of_fuel());
+}
@@ -1,14 +1,20 @@
-use anyhow::Result;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use ansi_term::Colour;
+use anyhow::{Context, Result};
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
-use tree_sitter::{Parser, Tree};
+use std::time::{Duration, SystemTime};
+use std::{env, thread};
+use tree_sitter::{Node, Parser, Point, Tree};
+use walkdir::WalkDir;
 
 #[cfg(unix)]
-use anyhow::{anyhow, Context};
+use anyhow::anyhow;
 #[cfg(unix)]
-use std::path::PathBuf;
-#[cfg(unix)]
-use std::process::{Child, ChildStdin, Command, Stdio};
+use std::process::{Child, ChildStdin, Stdio};
 
 #[cfg(unix)]
 const HTML_HEADER: &[u8] = b"
@@ -33,6 +39,149 @@ pub fn cancel_on_signal() -> Arc<AtomicUsize> {
     result
 }
 
+static COLOR_ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Decide whether output should contain ANSI colors, given the value of the global
+/// `--color` flag. With `auto`, colors are used only when stdout is a terminal and
+/// `NO_COLOR` is not set.
+pub fn init_color(choice: &str) {
+    let enabled = match choice {
+        "always" => true,
+        "never" => false,
+        _ => {
+            io::stdout().is_terminal()
+                && std::env::var_os("NO_COLOR").map_or(true, |value| value.is_empty())
+        }
+    };
+    COLOR_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+#[must_use]
+pub fn color_enabled() -> bool {
+    COLOR_ENABLED.load(Ordering::Relaxed)
+}
+
+/// Paint some text in the given color, unless colors have been disabled.
+pub fn paint(color: Colour, text: &str) -> String {
+    if color_enabled() {
+        color.paint(text).to_string()
+    } else {
+        text.to_string()
+    }
+}
+
+/// The unit that the columns of positions are measured in, for `--position-encoding`.
+/// Tree-sitter counts bytes, while editors that use the Language Server Protocol count
+/// UTF-16 code units by default.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum PositionEncoding {
+    #[default]
+    Utf8,
+    Utf16,
+    Utf32,
+}
+
+impl FromStr for PositionEncoding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "utf8" => Ok(Self::Utf8),
+            "utf16" => Ok(Self::Utf16),
+            "utf32" => Ok(Self::Utf32),
+            _ => Err(anyhow::anyhow!("Invalid position encoding '{s}'")),
+        }
+    }
+}
+
+impl PositionEncoding {
+    /// Measure the column of the point at the given byte offset in this encoding's code
+    /// units, by decoding the source code between the start of the line and the offset.
+    #[must_use]
+    pub fn convert_point(self, source: &[u8], offset: usize, point: Point) -> Point {
+        if self == Self::Utf8 {
+            return point;
+        }
+        let end = offset.min(source.len());
+        let line = String::from_utf8_lossy(&source[end.saturating_sub(point.column)..end]);
+        let column = if self == Self::Utf16 {
+            line.encode_utf16().count()
+        } else {
+            line.chars().count()
+        };
+        Point::new(point.row, column)
+    }
+
+    #[must_use]
+    pub fn start_position(self, node: Node, source: &[u8]) -> Point {
+        self.convert_point(source, node.start_byte(), node.start_position())
+    }
+
+    #[must_use]
+    pub fn end_position(self, node: Node, source: &[u8]) -> Point {
+        self.convert_point(source, node.end_byte(), node.end_position())
+    }
+}
+
+/// Print the time spent on one phase of processing a file to stderr as a line of
+/// JSON, for `--time-json`.
+pub fn print_time_json(path: &Path, phase: &str, duration: Duration) {
+    eprintln!(
+        "{}",
+        serde_json::json!({
+            "path": path.to_string_lossy(),
+            "phase": phase,
+            "micros": duration.as_micros() as u64,
+        })
+    );
+}
+
+/// Reports on stderr how far a run over many files has got, by rewriting a single
+/// `[N/total]` line. Nothing is printed when stderr is not a terminal.
+pub struct Progress {
+    current: usize,
+    total: usize,
+    enabled: bool,
+}
+
+impl Progress {
+    const BAR_WIDTH: usize = 30;
+
+    #[must_use]
+    pub fn new(total: usize, enabled: bool) -> Self {
+        Self {
+            current: 0,
+            total,
+            enabled: enabled && io::stderr().is_terminal(),
+        }
+    }
+
+    /// Advance to the next file.
+    pub fn tick(&mut self) {
+        self.current += 1;
+        if self.enabled {
+            let filled = Self::BAR_WIDTH * self.current / self.total.max(1);
+            let mut stderr = io::stderr().lock();
+            write!(
+                &mut stderr,
+                "\r[{}/{}] [{}{}]",
+                self.current,
+                self.total,
+                "#".repeat(filled),
+                "-".repeat(Self::BAR_WIDTH - filled)
+            )
+            .ok();
+            stderr.flush().ok();
+        }
+    }
+
+    pub fn finish(&self) {
+        if self.enabled {
+            eprintln!();
+        }
+    }
+}
+
 #[cfg(windows)]
 pub struct LogSession;
 
@@ -48,6 +197,82 @@ pub fn print_tree_graph(_tree: &Tree, _path: &str) -> Result<()> {
     Ok(())
 }
 
+/// Run the current command again without `--watch` whenever a file in one of the
+/// watched paths changes, until the process is interrupted. `before_run` is called
+/// before each run, and the terminal is cleared first when `clear_terminal` is set.
+///
+/// Each run happens in a new process, so that a recompiled parser gets loaded.
+pub fn watch_paths(
+    watched_paths: &[PathBuf],
+    clear_terminal: bool,
+    cancellation_flag: Option<&AtomicUsize>,
+    mut before_run: impl FnMut() -> Result<()>,
+) -> Result<()> {
+    let args = env::args_os()
+        .skip(1)
+        .filter(|arg| arg != "--watch")
+        .collect::<Vec<_>>();
+    let executable = env::current_exe().context("Failed to find the tree-sitter executable")?;
+    let clear_terminal = clear_terminal && io::stdout().is_terminal();
+
+    loop {
+        if clear_terminal {
+            print!("\x1B[2J\x1B[1;1H");
+            io::stdout().flush()?;
+        }
+        before_run()?;
+        Command::new(&executable)
+            .args(&args)
+            .status()
+            .with_context(|| format!("Failed to run {executable:?}"))?;
+
+        let modified_times = watched_modified_times(watched_paths);
+        while watched_modified_times(watched_paths) == modified_times {
+            if cancellation_flag.is_some_and(|flag| flag.load(Ordering::SeqCst) != 0) {
+                return Ok(());
+            }
+            thread::sleep(Duration::from_millis(500));
+        }
+    }
+}
+
+fn watched_modified_times(watched_paths: &[PathBuf]) -> Vec<(PathBuf, SystemTime)> {
+    watched_paths
+        .iter()
+        .flat_map(WalkDir::new)
+        .filter_map(std::result::Result::ok)
+        .filter(|e| e.file_type().is_file())
+        .filter_map(|e| Some((e.path().to_owned(), e.metadata().ok()?.modified().ok()?)))
+        .collect()
+}
+
+/// The path of the debug graph for the given input file, which is the given template
+/// with `{basename}` replaced by the input's file name.
+#[must_use]
+pub fn debug_graph_path(template: &str, input_path: &Path) -> String {
+    let basename = input_path.file_name().map_or_else(
+        || input_path.to_string_lossy(),
+        |name| name.to_string_lossy(),
+    );
+    template.replace("{basename}", &basename)
+}
+
+/// Check if the parser's last parse was halted because its wasm store ran out of the
+/// fuel given with `--wasm-fuel`.
+#[cfg(feature = "wasm")]
+pub fn ran_out_of_wasm_fuel(parser: &mut Parser) -> bool {
+    parser.take_wasm_store().map_or(false, |store| {
+        let ran_out_of_fuel = store.ran_out_of_fuel();
+        parser.set_wasm_store(store).unwrap();
+        ran_out_of_fuel
+    })
+}
+
+#[cfg(not(feature = "wasm"))]
+pub fn ran_out_of_wasm_fuel(_parser: &mut Parser) -> bool {
+    false
+}
+
 #[cfg(windows)]
 pub fn log_graphs(_parser: &mut Parser, _path: &str) -> Result<LogSession> {
     Ok(LogSession)
@@ -116,3 +341,58 @@ impl Drop for LogSession {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // "é" is two bytes in utf8 and one code unit in utf16, and "😀" is four bytes in
+    // utf8 and two code units in utf16. Both are a single utf32 code unit.
+    const SOURCE: &[u8] = "aé😀b\nx😀é\n".as_bytes();
+
+    #[test]
+    fn test_convert_point_on_first_line() {
+        let offset = "aé😀".len();
+        let point = Point::new(0, offset);
+        assert_eq!(
+            PositionEncoding::Utf8.convert_point(SOURCE, offset, point),
+            Point::new(0, 7)
+        );
+        assert_eq!(
+            PositionEncoding::Utf16.convert_point(SOURCE, offset, point),
+            Point::new(0, 4)
+        );
+        assert_eq!(
+            PositionEncoding::Utf32.convert_point(SOURCE, offset, point),
+            Point::new(0, 3)
+        );
+    }
+
+    #[test]
+    fn test_convert_point_on_later_line() {
+        // Only the text between the start of the line and the offset is measured.
+        let offset = "aé😀b\nx😀é".len();
+        let point = Point::new(1, "x😀é".len());
+        assert_eq!(
+            PositionEncoding::Utf16.convert_point(SOURCE, offset, point),
+            Point::new(1, 4)
+        );
+        assert_eq!(
+            PositionEncoding::Utf32.convert_point(SOURCE, offset, point),
+            Point::new(1, 3)
+        );
+    }
+
+    #[test]
+    fn test_convert_point_at_line_start() {
+        let point = Point::new(1, 0);
+        let offset = "aé😀b\n".len();
+        for encoding in [
+            PositionEncoding::Utf8,
+            PositionEncoding::Utf16,
+            PositionEncoding::Utf32,
+        ] {
+            assert_eq!(encoding.convert_point(SOURCE, offset, point), point);
+        }
+    }
+}
@@ -2,7 +2,7 @@ use super::generate::parse_grammar::GrammarJSON;
 use anyhow::{anyhow, Context, Result};
 use std::{fs, path::Path};
 use tree_sitter::wasm_stdlib_symbols;
-use tree_sitter_loader::Loader;
+use tree_sitter_loader::{format_command, Loader};
 use wasmparser::Parser;
 
 pub fn load_language_wasm_file(language_dir: &Path) -> Result<(String, Vec<u8>)> {
@@ -31,17 +31,31 @@ pub fn compile_language_to_wasm(
     language_dir: &Path,
     output_dir: &Path,
     force_docker: bool,
+    dry_run: bool,
 ) -> Result<()> {
     let grammar_name = get_grammar_name(language_dir)?;
     let output_filename = output_dir.join(format!("tree-sitter-{grammar_name}.wasm"));
     let src_path = language_dir.join("src");
     let scanner_path = loader.get_scanner_path(&src_path);
+    let scanner_filename = scanner_path
+        .as_ref()
+        .and_then(|p| Some(Path::new(p.file_name()?)));
+
+    if dry_run {
+        let command = loader.wasm_compile_command(
+            &grammar_name,
+            &src_path,
+            scanner_filename,
+            force_docker,
+        )?;
+        println!("{}", format_command(&command));
+        return Ok(());
+    }
+
     loader.compile_parser_to_wasm(
         &grammar_name,
         &src_path,
-        scanner_path
-            .as_ref()
-            .and_then(|p| Some(Path::new(p.file_name()?))),
+        scanner_filename,
         &output_filename,
         force_docker,
     )?;
@@ -163,7 +163,7 @@ where
     cancellation_flag: Option<&'a AtomicUsize>,
     layers: Vec<HighlightIterLayer<'a>>,
     iter_count: usize,
-    next_event: Option<HighlightEvent>,
+    next_event: Option<(HighlightEvent, &'a str)>,
     last_highlight_range: Option<(usize, usize, usize)>,
     apply_all_captures: bool,
 }
@@ -204,8 +204,36 @@ impl Highlighter {
         config: &'a HighlightConfiguration,
         source: &'a [u8],
         cancellation_flag: Option<&'a AtomicUsize>,
-        mut injection_callback: impl FnMut(&str) -> Option<&'a HighlightConfiguration> + 'a,
+        injection_callback: impl FnMut(&str) -> Option<&'a HighlightConfiguration> + 'a,
     ) -> Result<impl Iterator<Item = Result<HighlightEvent, Error>> + 'a, Error> {
+        self.highlight_iter(config, source, cancellation_flag, injection_callback)
+    }
+
+    /// Like [`Highlighter::highlight`], but each event is paired with the name of the
+    /// language whose layer produced it, which differs from `config`'s language for
+    /// the events inside of injections.
+    pub fn highlight_with_languages<'a>(
+        &'a mut self,
+        config: &'a HighlightConfiguration,
+        source: &'a [u8],
+        cancellation_flag: Option<&'a AtomicUsize>,
+        injection_callback: impl FnMut(&str) -> Option<&'a HighlightConfiguration> + 'a,
+    ) -> Result<impl Iterator<Item = Result<(HighlightEvent, &'a str), Error>> + 'a, Error> {
+        let mut iter =
+            self.highlight_iter(config, source, cancellation_flag, injection_callback)?;
+        Ok(iter::from_fn(move || iter.next_with_language()))
+    }
+
+    fn highlight_iter<'a, F>(
+        &'a mut self,
+        config: &'a HighlightConfiguration,
+        source: &'a [u8],
+        cancellation_flag: Option<&'a AtomicUsize>,
+        mut injection_callback: F,
+    ) -> Result<HighlightIter<'a, F>, Error>
+    where
+        F: FnMut(&str) -> Option<&'a HighlightConfiguration> + 'a,
+    {
         let layers = HighlightIterLayer::new(
             source,
             None,
@@ -419,6 +447,19 @@ impl HighlightConfiguration {
             .copied()
             .collect()
     }
+
+    // Return the list of this configuration's capture names that don't match any of the
+    // names passed to `configure`, and so are never highlighted. Private captures that
+    // start with an underscore are not included.
+    #[must_use]
+    pub fn unrecognized_capture_names(&self) -> Vec<&str> {
+        self.names()
+            .iter()
+            .zip(&self.highlight_indices)
+            .filter(|(n, highlight)| highlight.is_none() && !n.starts_with('_'))
+            .map(|(n, _)| *n)
+            .collect()
+    }
 }
 
 impl<'a> HighlightIterLayer<'a> {
@@ -661,17 +702,22 @@ where
         &mut self,
         offset: usize,
         event: Option<HighlightEvent>,
-    ) -> Option<Result<HighlightEvent, Error>> {
+    ) -> Option<Result<(HighlightEvent, &'a str), Error>> {
+        let config = self.layers[0].config;
+        let language_name = config.language_name.as_str();
         let result;
         if self.byte_offset < offset {
-            result = Some(Ok(HighlightEvent::Source {
-                start: self.byte_offset,
-                end: offset,
-            }));
+            result = Some(Ok((
+                HighlightEvent::Source {
+                    start: self.byte_offset,
+                    end: offset,
+                },
+                language_name,
+            )));
             self.byte_offset = offset;
-            self.next_event = event;
+            self.next_event = event.map(|event| (event, language_name));
         } else {
-            result = event.map(Ok);
+            result = event.map(|event| Ok((event, language_name)));
         }
         self.sort_layers();
         result
@@ -726,6 +772,16 @@ where
     type Item = Result<HighlightEvent, Error>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        self.next_with_language()
+            .map(|result| result.map(|(event, _)| event))
+    }
+}
+
+impl<'a, F> HighlightIter<'a, F>
+where
+    F: FnMut(&str) -> Option<&'a HighlightConfiguration> + 'a,
+{
+    fn next_with_language(&mut self) -> Option<Result<(HighlightEvent, &'a str), Error>> {
         'main: loop {
             // If we've already determined the next highlight boundary, just return it.
             if let Some(e) = self.next_event.take() {
@@ -747,10 +803,13 @@ where
             // If none of the layers have any more highlight boundaries, terminate.
             if self.layers.is_empty() {
                 return if self.byte_offset < self.source.len() {
-                    let result = Some(Ok(HighlightEvent::Source {
-                        start: self.byte_offset,
-                        end: self.source.len(),
-                    }));
+                    let result = Some(Ok((
+                        HighlightEvent::Source {
+                            start: self.byte_offset,
+                            end: self.source.len(),
+                        },
+                        self.language_name,
+                    )));
                     self.byte_offset = self.source.len();
                     result
                 } else {
@@ -819,6 +819,14 @@ extern "C" {
         error: *mut TSWasmError,
     ) -> *const TSLanguage;
 }
+extern "C" {
+    #[doc = " Limit the amount of fuel that the given wasm store can consume during each\n parse. When a parse runs out of fuel, it is halted as if it were cancelled.\n The store's engine must be configured to consume fuel. Pass zero to remove\n the limit."]
+    pub fn ts_wasm_store_set_fuel(arg1: *mut TSWasmStore, fuel: u64);
+}
+extern "C" {
+    #[doc = " Check if the last parse that used the given wasm store was halted because it\n ran out of fuel."]
+    pub fn ts_wasm_store_ran_out_of_fuel(arg1: *const TSWasmStore) -> bool;
+}
 extern "C" {
     #[doc = " Get the number of languages instantiated in the given wasm store."]
     pub fn ts_wasm_store_language_count(arg1: *const TSWasmStore) -> usize;
@@ -74,6 +74,20 @@ impl WasmStore {
         }
     }
 
+    /// Limit the amount of fuel that can be consumed during each parse. The
+    /// store's engine must have been created with fuel consumption enabled.
+    /// Pass zero to remove the limit.
+    pub fn set_fuel(&mut self, fuel: u64) {
+        unsafe { ffi::ts_wasm_store_set_fuel(self.0, fuel) }
+    }
+
+    /// Check if the last parse that used this store was halted because it ran
+    /// out of fuel.
+    #[must_use]
+    pub fn ran_out_of_fuel(&self) -> bool {
+        unsafe { ffi::ts_wasm_store_ran_out_of_fuel(self.0) }
+    }
+
     #[must_use]
     pub fn language_count(&self) -> usize {
         unsafe { ffi::ts_wasm_store_language_count(self.0) }
@@ -1201,6 +1201,20 @@ const TSLanguage *ts_wasm_store_load_language(
   TSWasmError *error
 );
 
+/**
+ * Limit the amount of fuel that the given wasm store can consume during each
+ * parse. When a parse runs out of fuel, it is halted as if it were cancelled.
+ * The store's engine must be configured to consume fuel. Pass zero to remove
+ * the limit.
+ */
+void ts_wasm_store_set_fuel(TSWasmStore *, uint64_t fuel);
+
+/**
+ * Check if the last parse that used the given wasm store was halted because it
+ * ran out of fuel.
+ */
+bool ts_wasm_store_ran_out_of_fuel(const TSWasmStore *);
+
 /**
  * Get the number of languages instantiated in the given wasm store.
  */
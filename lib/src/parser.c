@@ -1539,6 +1539,15 @@ static bool ts_parser__advance(
       }
     }
 
+    // If a wasm parser ran out of fuel, then halt the parse as if it had
+    // been cancelled.
+    if (self->wasm_store && ts_wasm_store_has_error(self->wasm_store)) {
+      if (lookahead.ptr) {
+        ts_subtree_release(&self->tree_pool, lookahead);
+      }
+      return false;
+    }
+
     // If a cancellation flag or a timeout was provided, then check every
     // time a fixed number of parse actions has been processed.
     if (++self->operation_count == OP_COUNT_PER_TIMEOUT_CHECK) {
@@ -86,6 +86,8 @@ struct TSWasmStore {
   uint16_t *fn_indices;
   wasm_globaltype_t *const_i32_type;
   wasm_globaltype_t *var_i32_type;
+  uint64_t fuel;
+  bool has_error;
 };
 
 typedef Array(char) StringData;
@@ -539,10 +541,18 @@ static bool ts_wasm_store__call_module_initializer(
   }
 }
 
+// Replace the amount of fuel available to the store. This fails harmlessly when
+// the store's engine was not configured to consume fuel.
+static void ts_wasm_store__set_fuel(wasmtime_context_t *context, uint64_t fuel) {
+  wasmtime_error_t *error = wasmtime_context_set_fuel(context, fuel);
+  if (error) wasmtime_error_delete(error);
+}
+
 TSWasmStore *ts_wasm_store_new(TSWasmEngine *engine, TSWasmError *wasm_error) {
   TSWasmStore *self = ts_malloc(sizeof(TSWasmStore));
   wasmtime_store_t *store = wasmtime_store_new(engine, self, NULL);
   wasmtime_context_t *context = wasmtime_store_context(store);
+  ts_wasm_store__set_fuel(context, UINT64_MAX);
   wasmtime_error_t *error = NULL;
   wasm_trap_t *trap = NULL;
   wasm_message_t message = WASM_EMPTY_VEC;
@@ -798,6 +808,14 @@ void ts_wasm_store_delete(TSWasmStore *self) {
   ts_free(self);
 }
 
+void ts_wasm_store_set_fuel(TSWasmStore *self, uint64_t fuel) {
+  self->fuel = fuel;
+}
+
+bool ts_wasm_store_ran_out_of_fuel(const TSWasmStore *self) {
+  return self->has_error;
+}
+
 size_t ts_wasm_store_language_count(const TSWasmStore *self) {
   size_t result = 0;
   for (unsigned i = 0; i < self->language_instances.size; i++) {
@@ -825,6 +843,9 @@ static bool ts_wasm_store__instantiate(
   wasmtime_extern_t *imports = NULL;
   wasmtime_context_t *context = wasmtime_store_context(self->store);
 
+  // Fuel is only limited while parsing, not while instantiating languages.
+  ts_wasm_store__set_fuel(context, UINT64_MAX);
+
   // Grow the function table to make room for the new functions.
   wasmtime_val_t initializer = {.kind = WASMTIME_FUNCREF};
   uint32_t prev_table_size;
@@ -1324,9 +1345,18 @@ bool ts_wasm_store_start(TSWasmStore *self, TSLexer *lexer, const TSLanguage *la
   if (!ts_wasm_store_add_language(self, language, &instance_index)) return false;
   self->current_lexer = lexer;
   self->current_instance = &self->language_instances.contents[instance_index];
+  self->has_error = false;
+  ts_wasm_store__set_fuel(
+    wasmtime_store_context(self->store),
+    self->fuel ? self->fuel : UINT64_MAX
+  );
   return true;
 }
 
+bool ts_wasm_store_has_error(const TSWasmStore *self) {
+  return self->has_error;
+}
+
 void ts_wasm_store_stop(TSWasmStore *self) {
   self->current_lexer = NULL;
   self->current_instance = NULL;
@@ -1349,6 +1379,15 @@ static void ts_wasm_store__call(
   wasmtime_error_t *error = wasmtime_func_call_unchecked(context, &func, args_and_results, args_and_results_len, &trap);
   assert(!error);
   if (trap) {
+    // Running out of fuel halts the parse, in the same way as a cancellation.
+    wasmtime_trap_code_t code;
+    if (wasmtime_trap_code(trap, &code) && code == WASMTIME_TRAP_CODE_OUT_OF_FUEL) {
+      wasm_trap_delete(trap);
+      self->has_error = true;
+      memset(args_and_results, 0, args_and_results_len * sizeof(wasmtime_val_raw_t));
+      return;
+    }
+
     wasm_message_t message;
     wasm_trap_message(trap, &message);
     fprintf(
@@ -1566,6 +1605,11 @@ void ts_wasm_store_stop(TSWasmStore *self) {
   (void)self;
 }
 
+bool ts_wasm_store_has_error(const TSWasmStore *self) {
+  (void)self;
+  return false;
+}
+
 bool ts_wasm_store_call_lex_main(TSWasmStore *self, TSStateId state) {
   (void)self;
   (void)state;
@@ -10,6 +10,7 @@ extern "C" {
 
 bool ts_wasm_store_start(TSWasmStore *, TSLexer *, const TSLanguage *);
 void ts_wasm_store_stop(TSWasmStore *);
+bool ts_wasm_store_has_error(const TSWasmStore *);
 
 bool ts_wasm_store_call_lex_main(TSWasmStore *, TSStateId);
 bool ts_wasm_store_call_lex_keyword(TSWasmStore *, TSStateId);